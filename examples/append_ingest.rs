@@ -0,0 +1,93 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use greptimedb_ingester::api::v1::*;
+use greptimedb_ingester::helpers::schema::{field, tag, timestamp};
+use greptimedb_ingester::helpers::values::{string_value, timestamp_millisecond_value};
+use greptimedb_ingester::{ClientBuilder, Database, DEFAULT_SCHEMA_NAME};
+
+#[tokio::main]
+async fn main() {
+    let greptimedb_endpoint =
+        std::env::var("GREPTIMEDB_ENDPOINT").unwrap_or_else(|_| "localhost:4001".to_owned());
+
+    let greptimedb_dbname =
+        std::env::var("GREPTIMEDB_DBNAME").unwrap_or_else(|_| DEFAULT_SCHEMA_NAME.to_owned());
+
+    let grpc_client = ClientBuilder::default()
+        .peers(vec![&greptimedb_endpoint])
+        .build();
+
+    let client = Database::new_with_dbname(greptimedb_dbname, grpc_client);
+
+    // Rows sharing the same tags and timestamp are deduplicated by default,
+    // only the last of the two log lines below is kept.
+    match client
+        .row_insert(to_insert_requests("app_logs_dedup", log_records()))
+        .await
+    {
+        Ok(rows) => println!("Rows written (default): {rows}"),
+        Err(e) => eprintln!("Error: {e}"),
+    };
+
+    // In append mode, both log lines are kept and writes skip the merge work.
+    match client
+        .append_insert(to_insert_requests("app_logs_append", log_records()))
+        .await
+    {
+        Ok(rows) => println!("Rows written (append): {rows}"),
+        Err(e) => eprintln!("Error: {e}"),
+    };
+}
+
+fn log_records() -> Vec<(i64, &'static str, &'static str)> {
+    vec![
+        (1686109527000, "host1", "connection accepted"),
+        (1686109527000, "host1", "connection closed"),
+    ]
+}
+
+fn logs_schema() -> Vec<ColumnSchema> {
+    vec![
+        timestamp("ts", ColumnDataType::TimestampMillisecond),
+        tag("host", ColumnDataType::String),
+        field("message", ColumnDataType::String),
+    ]
+}
+
+fn to_insert_requests(
+    table_name: &str,
+    records: Vec<(i64, &'static str, &'static str)>,
+) -> RowInsertRequests {
+    let rows = records
+        .into_iter()
+        .map(|(ts, host, message)| Row {
+            values: vec![
+                timestamp_millisecond_value(ts),
+                string_value(host.to_owned()),
+                string_value(message.to_owned()),
+            ],
+        })
+        .collect();
+
+    RowInsertRequests {
+        inserts: vec![RowInsertRequest {
+            table_name: table_name.to_owned(),
+            rows: Some(Rows {
+                schema: logs_schema(),
+                rows,
+            }),
+        }],
+    }
+}
@@ -86,6 +86,10 @@ impl ChannelManager {
             client_tls_config = client_tls_config.identity(client_identity);
         }
 
+        if let Some(name) = config.tls_server_name {
+            client_tls_config = client_tls_config.domain_name(name);
+        }
+
         cm.client_tls_config = Some(client_tls_config);
 
         Ok(cm)
@@ -193,6 +197,18 @@ impl ChannelManager {
         if let Some(enabled) = self.config.http2_adaptive_window {
             endpoint = endpoint.http2_adaptive_window(enabled);
         }
+        if let Some(user_agent) = &self.config.user_agent {
+            endpoint = endpoint
+                .user_agent(user_agent.as_str())
+                .context(CreateChannelSnafu)?;
+        }
+        if let Some(authority) = &self.config.authority {
+            let origin = Endpoint::new(format!("{scheme}://{authority}"))
+                .context(CreateChannelSnafu)?
+                .uri()
+                .clone();
+            endpoint = endpoint.origin(origin);
+        }
         if let Some(tls_config) = &self.client_tls_config {
             endpoint = endpoint
                 .tls_config(tls_config.clone())
@@ -208,8 +224,11 @@ impl ChannelManager {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
 pub struct ClientTlsOption {
-    /// Path to server CA file, use system CA when not configured
+    /// Path to server CA file, use the roots selected by the `tls-roots` or
+    /// `tls-webpki-roots` feature when not configured
     pub server_ca_cert_path: Option<PathBuf>,
     /// the file path to client certificate
     pub client_cert_path: Option<PathBuf>,
@@ -217,6 +236,13 @@ pub struct ClientTlsOption {
     pub client_key_path: Option<PathBuf>,
 }
 
+/// The gRPC channel settings of a [`ChannelManager`].
+///
+/// The HTTP/2 flow-control windows default to 65,535 bytes, which caps the
+/// throughput of a connection to about one window per round-trip. For bulk
+/// ingestion over high-latency links, raise them to a few MiB, e.g. 4MiB per
+/// stream and 8MiB per connection, or enable [adaptive
+/// windows](ChannelConfig::http2_adaptive_window) to let hyper size them.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ChannelConfig {
     pub timeout: Option<Duration>,
@@ -232,6 +258,9 @@ pub struct ChannelConfig {
     pub tcp_keepalive: Option<Duration>,
     pub tcp_nodelay: bool,
     pub client_tls: Option<ClientTlsOption>,
+    pub user_agent: Option<String>,
+    pub authority: Option<String>,
+    pub tls_server_name: Option<String>,
 }
 
 impl Default for ChannelConfig {
@@ -250,6 +279,9 @@ impl Default for ChannelConfig {
             tcp_keepalive: None,
             tcp_nodelay: true,
             client_tls: None,
+            user_agent: None,
+            authority: None,
+            tls_server_name: None,
         }
     }
 }
@@ -376,6 +408,35 @@ impl ChannelConfig {
             ..self
         }
     }
+
+    /// Set the user-agent header sent on every request, e.g. for proxies
+    /// routing on it. Uses tonic's default otherwise.
+    pub fn user_agent(self, user_agent: impl Into<String>) -> Self {
+        Self {
+            user_agent: Some(user_agent.into()),
+            ..self
+        }
+    }
+
+    /// Send `authority`, e.g. `db.example.com:4001`, as the HTTP/2
+    /// `:authority` of every request instead of the dialed address, for load
+    /// balancers routing on it while peers are given as IPs.
+    pub fn override_authority(self, authority: impl Into<String>) -> Self {
+        Self {
+            authority: Some(authority.into()),
+            ..self
+        }
+    }
+
+    /// Validate the server certificate against `name`, also sent as SNI,
+    /// instead of the host of the dialed address, e.g. when peers are given
+    /// as IPs. Only applies with TLS enabled.
+    pub fn tls_server_name(self, name: impl Into<String>) -> Self {
+        Self {
+            tls_server_name: Some(name.into()),
+            ..self
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -517,6 +578,9 @@ mod tests {
                 tcp_keepalive: None,
                 tcp_nodelay: true,
                 client_tls: None,
+                user_agent: None,
+                authority: None,
+                tls_server_name: None,
             },
             default_cfg
         );
@@ -538,7 +602,10 @@ mod tests {
                 server_ca_cert_path: Some("some_server_path".into()),
                 client_cert_path: Some("some_cert_path".into()),
                 client_key_path: Some("some_key_path".into()),
-            });
+            })
+            .user_agent("my-app/1.0")
+            .override_authority("db.example.com:4001")
+            .tls_server_name("db.example.com");
 
         assert_eq!(
             ChannelConfig {
@@ -559,6 +626,9 @@ mod tests {
                     client_cert_path: Some("some_cert_path".into()),
                     client_key_path: Some("some_key_path".into()),
                 }),
+                user_agent: Some("my-app/1.0".to_string()),
+                authority: Some("db.example.com:4001".to_string()),
+                tls_server_name: Some("db.example.com".to_string()),
             },
             cfg
         );
@@ -579,7 +649,9 @@ mod tests {
             .http2_keep_alive_while_idle(true)
             .http2_adaptive_window(true)
             .tcp_keepalive(Duration::from_secs(2))
-            .tcp_nodelay(true);
+            .tcp_nodelay(true)
+            .user_agent("my-app/1.0")
+            .override_authority("db.example.com:4001");
         let mgr = ChannelManager {
             pool,
             config,
@@ -12,25 +12,33 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant, SystemTime};
 
 use crate::api::v1::greptime_database_client::GreptimeDatabaseClient;
 use crate::api::v1::health_check_client::HealthCheckClient;
 use crate::api::v1::HealthCheckRequest;
 use crate::channel_manager::ChannelManager;
+use dashmap::DashMap;
+use futures::{Stream, StreamExt};
 use parking_lot::RwLock;
-use snafu::OptionExt;
+use snafu::{ensure, OptionExt};
 use tonic::codec::CompressionEncoding;
 use tonic::transport::Channel;
 
 use crate::load_balance::{LoadBalance, Loadbalancer};
-use crate::{error, Result};
+use crate::rate_limit::{ConcurrencyLimiter, RateLimiter};
+use crate::{error, Error, Result};
 use derive_builder::Builder;
 
 const MAX_MESSAGE_SIZE: usize = 512 * 1024 * 1024;
 
 pub(crate) struct DatabaseClient {
     pub(crate) inner: GreptimeDatabaseClient<Channel>,
+    pub(crate) peer: String,
 }
 
 #[derive(Clone, Debug, Default)]
@@ -38,14 +46,44 @@ pub struct Client {
     inner: Arc<Inner>,
 }
 
-#[derive(Default)]
 pub struct ClientBuilder {
     channel_manager: ChannelManager,
     load_balance: Loadbalancer,
     compression: Compression,
+    accept_compression: bool,
+    client_id: Option<String>,
+    rate_limit: Option<u32>,
+    trace_context: Option<TraceContextProvider>,
+    max_encoding_message_size: Option<usize>,
+    compression_threshold: Option<usize>,
+    max_concurrent_requests: Option<usize>,
+    fail_fast_on_max_concurrency: bool,
+    send_empty_requests: bool,
+    keep_warm_interval: Option<Duration>,
     peers: Vec<String>,
 }
 
+impl Default for ClientBuilder {
+    fn default() -> Self {
+        Self {
+            channel_manager: ChannelManager::default(),
+            load_balance: Loadbalancer::default(),
+            compression: Compression::default(),
+            accept_compression: true,
+            client_id: None,
+            rate_limit: None,
+            trace_context: None,
+            max_encoding_message_size: None,
+            compression_threshold: None,
+            max_concurrent_requests: None,
+            fail_fast_on_max_concurrency: false,
+            send_empty_requests: false,
+            keep_warm_interval: None,
+            peers: Vec::new(),
+        }
+    }
+}
+
 impl ClientBuilder {
     pub fn channel_manager(mut self, channel_manager: ChannelManager) -> Self {
         self.channel_manager = channel_manager;
@@ -57,11 +95,133 @@ impl ClientBuilder {
         self
     }
 
+    /// The compression of the requests sent, gzip by default.
+    ///
+    /// It only applies to sending: responses are accepted compressed as set
+    /// by [`ClientBuilder::accept_compression`], so a write-heavy client can
+    /// send with [`Compression::None`] and still get compressed responses.
     pub fn compression(mut self, compression: Compression) -> Self {
         self.compression = compression;
         self
     }
 
+    /// Whether to advertise and decompress gzip/zstd compressed responses.
+    ///
+    /// Enabled by default. Disable it to skip response decompression entirely,
+    /// independently of the [`Compression`] used for sending.
+    pub fn accept_compression(mut self, enabled: bool) -> Self {
+        self.accept_compression = enabled;
+        self
+    }
+
+    /// Identify this client, e.g. `my-app/1.2.0`, on every request it sends.
+    ///
+    /// The id is carried in the `x-greptime-client-id` gRPC metadata so the
+    /// server can attribute traffic per client. It must be valid ASCII, or
+    /// requests fail with [`Error::InvalidAscii`](crate::Error::InvalidAscii).
+    pub fn client_id(mut self, client_id: &str) -> Self {
+        self.client_id = Some(client_id.to_string());
+        self
+    }
+
+    /// Limit write requests to `requests_per_sec`, across all databases and
+    /// streaming inserters of the client.
+    ///
+    /// Writes over the budget wait for it rather than fail. The limit can be
+    /// changed later with [`Client::set_rate_limit`].
+    pub fn rate_limit(mut self, requests_per_sec: u32) -> Self {
+        self.rate_limit = Some(requests_per_sec);
+        self
+    }
+
+    /// Propagate distributed tracing context on every request.
+    ///
+    /// `provider` is called for each request, and each streaming inserter when
+    /// it is created, and returns the context headers of the current span,
+    /// e.g. the W3C `traceparent` and `tracestate`, typically from an
+    /// OpenTelemetry propagator. They are sent as gRPC metadata, so server-side
+    /// spans link to the client's. Header names must be valid lowercase
+    /// metadata keys and values valid ASCII, or requests fail with
+    /// [`Error::InvalidAscii`](crate::Error::InvalidAscii).
+    pub fn trace_context_provider<F>(mut self, provider: F) -> Self
+    where
+        F: Fn() -> HashMap<String, String> + Send + Sync + 'static,
+    {
+        self.trace_context = Some(TraceContextProvider(Arc::new(provider)));
+        self
+    }
+
+    /// Limit the encoded size of a request message to `limit` bytes.
+    ///
+    /// Larger requests fail with
+    /// [`Error::MessageTooLarge`](crate::Error::MessageTooLarge) before being
+    /// sent. Unlimited by default.
+    pub fn max_encoding_message_size(mut self, limit: usize) -> Self {
+        self.max_encoding_message_size = Some(limit);
+        self
+    }
+
+    /// Only compress unary requests of at least `bytes` when encoded, smaller
+    /// ones are sent uncompressed to save the CPU cost of compressing tiny
+    /// messages, where it gains little.
+    ///
+    /// Applies to the client's compression and the one given to
+    /// [`Database::row_insert_with_compression`](crate::Database::row_insert_with_compression).
+    /// Streaming inserts pick their compression once for the whole stream, so
+    /// they always compress. Unset by default, compressing every request.
+    pub fn compression_threshold(mut self, bytes: usize) -> Self {
+        self.compression_threshold = Some(bytes);
+        self
+    }
+
+    /// Limit the unary requests in flight, e.g. `row_insert` calls, to `n`
+    /// across the client.
+    ///
+    /// Requests over the limit wait for one in flight to complete, see
+    /// [`ClientBuilder::fail_fast_on_max_concurrency`] to fail them instead.
+    /// Streaming inserts are not counted. `0` means no limit.
+    pub fn max_concurrent_requests(mut self, n: usize) -> Self {
+        self.max_concurrent_requests = Some(n);
+        self
+    }
+
+    /// Fail requests over the [concurrency
+    /// limit](ClientBuilder::max_concurrent_requests) with
+    /// [`Error::TooManyRequests`](crate::Error::TooManyRequests) rather than
+    /// waiting, so callers can shed load.
+    pub fn fail_fast_on_max_concurrency(mut self, enabled: bool) -> Self {
+        self.fail_fast_on_max_concurrency = enabled;
+        self
+    }
+
+    /// Send row inserts without any row to the server rather than skipping
+    /// them, for callers relying on the server seeing every request.
+    ///
+    /// Disabled by default: unary inserts without rows return `Ok(0)` without
+    /// a round trip, and streaming inserts drop them.
+    pub fn send_empty_requests(mut self, enabled: bool) -> Self {
+        self.send_empty_requests = enabled;
+        self
+    }
+
+    /// Health check every peer each `interval` from a background task, to
+    /// keep idle connections warm and notice dead peers before a write does.
+    ///
+    /// Environments dropping idle connections despite keepalives then don't
+    /// add a reconnect to the first request after a quiet period. Each ping
+    /// waits at most `interval` and feeds the peer health reported by
+    /// [`Client::peer_status`] and the load balancer, like
+    /// [`Client::health_check_all`]. The task stops once every clone of the
+    /// client is dropped. Disabled by default.
+    ///
+    /// The task is spawned by [`ClientBuilder::build`], which must then run
+    /// within a Tokio runtime. Outside of one, a warning is logged and the
+    /// client is built without keep-warm rather than panicking.
+    pub fn keep_warm_interval(mut self, interval: Duration) -> Self {
+        self.keep_warm_interval = Some(interval);
+        self
+    }
+
     pub fn peers<U, A>(mut self, peers: A) -> Self
     where
         U: AsRef<str>,
@@ -71,21 +231,78 @@ impl ClientBuilder {
         self
     }
 
+    /// Set peers from already-resolved socket addresses, e.g. handed over by
+    /// an external service discovery.
+    ///
+    /// Peers given as IP addresses are dialed directly, skipping the DNS
+    /// lookup a hostname peer needs on every reconnect, so connect latency
+    /// doesn't depend on the resolver. Replaces the peers set with
+    /// [`ClientBuilder::peers`].
+    pub fn socket_addrs<I>(mut self, addrs: I) -> Self
+    where
+        I: IntoIterator<Item = SocketAddr>,
+    {
+        self.peers = addrs.into_iter().map(|addr| addr.to_string()).collect();
+        self
+    }
+
     pub fn build(self) -> Client {
         let inner = InnerBuilder::default()
             .channel_manager(self.channel_manager)
             .load_balance(self.load_balance)
             .compression(self.compression)
+            .accept_compression(self.accept_compression)
+            .client_id(self.client_id)
+            .rate_limiter(Arc::new(RateLimiter::new(self.rate_limit)))
+            .trace_context(self.trace_context)
+            .max_encoding_message_size(self.max_encoding_message_size)
+            .compression_threshold(self.compression_threshold)
+            .send_empty_requests(self.send_empty_requests)
+            .concurrency_limiter(Arc::new(ConcurrencyLimiter::new(
+                self.max_concurrent_requests,
+                self.fail_fast_on_max_concurrency,
+            )))
             .peers(self.peers)
             .build()
             .unwrap();
-        Client {
+        let client = Client {
             inner: Arc::new(inner),
+        };
+        if let Some(interval) = self.keep_warm_interval {
+            match tokio::runtime::Handle::try_current() {
+                Ok(runtime) => {
+                    runtime.spawn(keep_warm(Arc::downgrade(&client.inner), interval));
+                }
+                Err(_) => log::warn!(
+                    "Client built outside of a Tokio runtime, keep-warm interval is ignored"
+                ),
+            }
         }
+        client
     }
 }
 
-#[derive(Debug, Clone, Default)]
+/// Ping every peer of the client each `interval`, until the client is gone.
+async fn keep_warm(inner: Weak<Inner>, interval: Duration) {
+    loop {
+        tokio::time::sleep(interval).await;
+        let Some(inner) = inner.upgrade() else {
+            break;
+        };
+        Client { inner }.health_check_all(interval).await;
+    }
+}
+
+/// The outcome of a peer's health check, see [`Client::health_check_all`].
+#[derive(Debug, Clone)]
+pub struct PeerHealth {
+    pub peer: String,
+    pub healthy: bool,
+    /// The round-trip time of the health check, when it succeeded.
+    pub latency: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub enum Compression {
     #[default]
     Gzip,
@@ -93,13 +310,82 @@ pub enum Compression {
     None,
 }
 
-#[derive(Debug, Default, Builder)]
+#[derive(Clone)]
+struct TraceContextProvider(Arc<dyn Fn() -> HashMap<String, String> + Send + Sync>);
+
+impl fmt::Debug for TraceContextProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("TraceContextProvider")
+    }
+}
+
+/// The last-known status of a configured peer, see [`Client::peer_status`].
+#[derive(Debug, Clone)]
+pub struct PeerStatus {
+    pub peer: String,
+    /// Whether the latest request to the peer succeeded, `None` if the client
+    /// hasn't sent it any yet.
+    pub healthy: Option<bool>,
+    /// When a request to the peer last succeeded.
+    pub last_success: Option<SystemTime>,
+    /// The error of the latest failed request to the peer.
+    pub last_error: Option<String>,
+}
+
+/// What the client learned about a peer from the requests sent to it.
+#[derive(Debug, Clone, Default)]
+struct PeerState {
+    /// Whether the latest request to the peer succeeded.
+    ready: bool,
+    last_success: Option<SystemTime>,
+    last_error: Option<String>,
+}
+
+#[derive(Debug, Builder)]
 struct Inner {
     channel_manager: ChannelManager,
     #[builder(setter(custom))]
     peers: Arc<RwLock<Vec<String>>>,
     load_balance: Loadbalancer,
     compression: Compression,
+    #[builder(default = "true")]
+    accept_compression: bool,
+    #[builder(default)]
+    client_id: Option<String>,
+    #[builder(default)]
+    rate_limiter: Arc<RateLimiter>,
+    #[builder(default)]
+    trace_context: Option<TraceContextProvider>,
+    #[builder(default)]
+    max_encoding_message_size: Option<usize>,
+    #[builder(default)]
+    compression_threshold: Option<usize>,
+    #[builder(default)]
+    send_empty_requests: bool,
+    #[builder(default)]
+    peer_states: DashMap<String, PeerState>,
+    #[builder(default)]
+    concurrency_limiter: Arc<ConcurrencyLimiter>,
+}
+
+impl Default for Inner {
+    fn default() -> Self {
+        Self {
+            channel_manager: ChannelManager::default(),
+            peers: Arc::default(),
+            load_balance: Loadbalancer::default(),
+            compression: Compression::default(),
+            accept_compression: true,
+            client_id: None,
+            rate_limiter: Arc::default(),
+            trace_context: None,
+            max_encoding_message_size: None,
+            compression_threshold: None,
+            send_empty_requests: false,
+            peer_states: DashMap::default(),
+            concurrency_limiter: Arc::default(),
+        }
+    }
 }
 
 impl InnerBuilder {
@@ -175,6 +461,141 @@ impl Client {
         self.inner.set_peers(urls);
     }
 
+    /// The compression used for sending requests.
+    pub fn compression(&self) -> Compression {
+        self.inner.compression
+    }
+
+    /// The load balancer selecting peers for requests.
+    pub fn load_balance_kind(&self) -> &Loadbalancer {
+        &self.inner.load_balance
+    }
+
+    /// Change the write rate limit at runtime, e.g. to back off when the
+    /// server is overloaded. `None` or `0` removes the limit.
+    pub fn set_rate_limit(&self, requests_per_sec: Option<u32>) {
+        self.inner.rate_limiter.set_rate(requests_per_sec);
+    }
+
+    /// The current write rate limit, see [`ClientBuilder::rate_limit`].
+    pub fn rate_limit(&self) -> Option<u32> {
+        self.inner.rate_limiter.rate()
+    }
+
+    /// The number of unary requests currently in flight, e.g. to export as a
+    /// metric.
+    pub fn in_flight_requests(&self) -> usize {
+        self.inner.concurrency_limiter.in_flight()
+    }
+
+    pub(crate) fn concurrency_limiter(&self) -> &ConcurrencyLimiter {
+        &self.inner.concurrency_limiter
+    }
+
+    pub(crate) fn rate_limiter(&self) -> &Arc<RateLimiter> {
+        &self.inner.rate_limiter
+    }
+
+    pub(crate) fn client_id(&self) -> Option<&str> {
+        self.inner.client_id.as_deref()
+    }
+
+    pub(crate) fn max_encoding_message_size(&self) -> Option<usize> {
+        self.inner.max_encoding_message_size
+    }
+
+    pub(crate) fn send_empty_requests(&self) -> bool {
+        self.inner.send_empty_requests
+    }
+
+    /// The compression to send a unary request of `size` encoded bytes with,
+    /// `compression` unless the request is under the
+    /// [threshold](ClientBuilder::compression_threshold).
+    pub(crate) fn compression_for(&self, compression: Compression, size: usize) -> Compression {
+        match self.inner.compression_threshold {
+            Some(threshold) if size < threshold => Compression::None,
+            _ => compression,
+        }
+    }
+
+    /// The trace context headers of the current span, empty without a
+    /// provider.
+    pub(crate) fn trace_context(&self) -> HashMap<String, String> {
+        self.inner
+            .trace_context
+            .as_ref()
+            .map(|provider| (provider.0)())
+            .unwrap_or_default()
+    }
+
+    /// Report the outcome of a request sent to `peer` to the load balancer.
+    pub(crate) fn record_result(&self, peer: &str, result: std::result::Result<(), &Error>) {
+        {
+            let mut state = self.inner.peer_states.entry(peer.to_string()).or_default();
+            state.ready = result.is_ok();
+            match result {
+                Ok(()) => state.last_success = Some(SystemTime::now()),
+                Err(e) => state.last_error = Some(e.to_string()),
+            }
+        }
+        self.inner.load_balance.record_result(peer, result);
+    }
+
+    /// The last-known status of every configured peer, in order, as learned
+    /// from the requests and [health checks](Client::health_check_all) the
+    /// client sent. Peers never contacted have an unknown status.
+    pub fn peer_status(&self) -> Vec<PeerStatus> {
+        self.inner
+            .peers
+            .read()
+            .iter()
+            .map(|peer| match self.inner.peer_states.get(peer) {
+                Some(state) => PeerStatus {
+                    peer: peer.clone(),
+                    healthy: Some(state.ready),
+                    last_success: state.last_success,
+                    last_error: state.last_error.clone(),
+                },
+                None => PeerStatus {
+                    peer: peer.clone(),
+                    healthy: None,
+                    last_success: None,
+                    last_error: None,
+                },
+            })
+            .collect()
+    }
+
+    /// Whether `peer` is ready to serve requests, without sending one.
+    ///
+    /// tonic doesn't expose the connectivity state of its channels, so this
+    /// tells whether the latest request the client sent to `peer`, including
+    /// [health checks](Client::health_check), succeeded. A peer never contacted
+    /// isn't ready.
+    pub fn channel_ready(&self, peer: &str) -> bool {
+        self.inner
+            .peer_states
+            .get(peer)
+            .is_some_and(|state| state.ready)
+    }
+
+    /// The peers to try a request on in turn until one serves it: the one
+    /// picked by the load balancer first, then the others in configured
+    /// order, [ready](Client::channel_ready) ones first.
+    pub(crate) fn failover_order(&self) -> Vec<String> {
+        let first = self.inner.get_peer();
+        let mut rest: Vec<String> = self
+            .inner
+            .peers
+            .read()
+            .iter()
+            .filter(|peer| Some(*peer) != first.as_ref())
+            .cloned()
+            .collect();
+        rest.sort_by_key(|peer| !self.channel_ready(peer));
+        first.into_iter().chain(rest).collect()
+    }
+
     fn find_channel(&self) -> Result<(String, Channel)> {
         let addr = self
             .inner
@@ -188,13 +609,45 @@ impl Client {
         Ok((addr, channel))
     }
 
-    pub(crate) fn make_database_client(&self) -> Result<DatabaseClient> {
-        let (_, channel) = self.find_channel()?;
-        let mut client = GreptimeDatabaseClient::new(channel)
-            .max_decoding_message_size(MAX_MESSAGE_SIZE)
-            .accept_compressed(CompressionEncoding::Gzip)
-            .accept_compressed(CompressionEncoding::Zstd);
-        match self.inner.compression {
+    /// Make a client of the database service, sending requests with
+    /// `compression`.
+    pub(crate) fn make_database_client(&self, compression: Compression) -> Result<DatabaseClient> {
+        let (peer, channel) = self.find_channel()?;
+        Ok(self.database_client(peer, channel, compression))
+    }
+
+    /// Like `make_database_client`, but to `peer` rather than one picked by
+    /// the load balancer. `peer` must be configured.
+    pub(crate) fn make_database_client_to_peer(
+        &self,
+        peer: &str,
+        compression: Compression,
+    ) -> Result<DatabaseClient> {
+        ensure!(
+            self.inner.peers.read().iter().any(|p| p == peer),
+            error::UnknownPeerSnafu { peer }
+        );
+        let channel = self.inner.channel_manager.get(peer)?;
+        Ok(self.database_client(peer.to_string(), channel, compression))
+    }
+
+    fn database_client(
+        &self,
+        peer: String,
+        channel: Channel,
+        compression: Compression,
+    ) -> DatabaseClient {
+        let mut client =
+            GreptimeDatabaseClient::new(channel).max_decoding_message_size(MAX_MESSAGE_SIZE);
+        if let Some(limit) = self.inner.max_encoding_message_size {
+            client = client.max_encoding_message_size(limit);
+        }
+        if self.inner.accept_compression {
+            client = client
+                .accept_compressed(CompressionEncoding::Gzip)
+                .accept_compressed(CompressionEncoding::Zstd);
+        }
+        match compression {
             Compression::Gzip => {
                 client = client.send_compressed(CompressionEncoding::Gzip);
             }
@@ -203,14 +656,91 @@ impl Client {
             }
             Compression::None => {}
         }
-        Ok(DatabaseClient { inner: client })
+        DatabaseClient {
+            inner: client,
+            peer,
+        }
     }
 
     pub async fn health_check(&self) -> Result<()> {
-        let (_, channel) = self.find_channel()?;
+        self.ping().await.map(|_| ())
+    }
+
+    /// Send a health check to a peer picked by the load balancer and measure
+    /// its round-trip time, which is also reported to the load balancer.
+    pub(crate) async fn ping(&self) -> Result<Duration> {
+        let (peer, channel) = self.find_channel()?;
+        self.ping_peer(&peer, channel, None).await
+    }
+
+    /// Health check every configured peer concurrently, each waiting at most
+    /// `timeout` for its response.
+    ///
+    /// Probes reuse the cached channels of the [`ChannelManager`], and their
+    /// outcomes are reported to the load balancer like other requests.
+    pub async fn health_check_all(&self, timeout: Duration) -> Vec<PeerHealth> {
+        let peers = self.inner.peers.read().clone();
+        let probes = peers.into_iter().map(|peer| self.probe(peer, timeout));
+        futures::future::join_all(probes).await
+    }
+
+    /// Connect to every configured peer ahead of the first writes, dialing at
+    /// most `concurrency` peers at a time, each probed by a health check
+    /// waiting at most `timeout`.
+    ///
+    /// Bounding the dials avoids a spike of sockets and handshakes when the
+    /// client has many peers. The outcomes are yielded as they complete, and
+    /// reported like [`Client::health_check_all`]'s. A `concurrency` of `0` is
+    /// treated as `1`.
+    pub fn warmup(
+        &self,
+        concurrency: usize,
+        timeout: Duration,
+    ) -> impl Stream<Item = PeerHealth> + '_ {
+        let peers = self.inner.peers.read().clone();
+        futures::stream::iter(peers)
+            .map(move |peer| self.probe(peer, timeout))
+            .buffer_unordered(concurrency.max(1))
+    }
+
+    async fn probe(&self, peer: String, timeout: Duration) -> PeerHealth {
+        let result = match self.inner.channel_manager.get(&peer) {
+            Ok(channel) => self.ping_peer(&peer, channel, Some(timeout)).await,
+            Err(e) => {
+                self.record_result(&peer, Err(&e));
+                Err(e)
+            }
+        };
+        PeerHealth {
+            healthy: result.is_ok(),
+            latency: result.ok(),
+            peer,
+        }
+    }
+
+    async fn ping_peer(
+        &self,
+        peer: &str,
+        channel: Channel,
+        timeout: Option<Duration>,
+    ) -> Result<Duration> {
         let mut client = HealthCheckClient::new(channel);
-        client.health_check(HealthCheckRequest {}).await?;
-        Ok(())
+        let start = Instant::now();
+        let health_check = client.health_check(HealthCheckRequest {});
+        let response = match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, health_check)
+                .await
+                .ok()
+                .context(error::TimeoutSnafu { timeout }),
+            None => Ok(health_check.await),
+        };
+        let result =
+            response.and_then(|response| response.map(|_| start.elapsed()).map_err(Error::from));
+        self.record_result(peer, result.as_ref().map(|_| ()));
+        if let Ok(rtt) = result {
+            self.inner.load_balance.record_latency(peer, rtt);
+        }
+        result
     }
 }
 
@@ -228,8 +758,10 @@ where
 #[cfg(test)]
 mod tests {
     use std::collections::HashSet;
+    use std::time::Duration;
 
-    use super::Inner;
+    use super::{ClientBuilder, Compression, Inner};
+    use crate::error;
     use crate::load_balance::Loadbalancer;
 
     fn mock_peers() -> Vec<String> {
@@ -258,4 +790,223 @@ mod tests {
             assert!(all.contains(&inner.get_peer().unwrap()));
         }
     }
+
+    #[tokio::test]
+    async fn test_accept_compression() {
+        assert!(Inner::default().accept_compression);
+
+        let client = ClientBuilder::default().build();
+        assert!(client.inner.accept_compression);
+
+        let client = ClientBuilder::default().accept_compression(false).build();
+        assert!(!client.inner.accept_compression);
+
+        // Sending and accepting are set independently.
+        let client = ClientBuilder::default()
+            .compression(Compression::None)
+            .build();
+        assert!(client.inner.accept_compression);
+        assert_eq!(Compression::None, client.compression());
+    }
+
+    #[tokio::test]
+    async fn test_config_getters() {
+        let client = ClientBuilder::default().build();
+        assert_eq!(Compression::Gzip, client.compression());
+        assert!(matches!(
+            client.load_balance_kind(),
+            Loadbalancer::Random(crate::load_balance::Random)
+        ));
+
+        let client = ClientBuilder::default()
+            .compression(Compression::Zstd)
+            .build();
+        assert_eq!(Compression::Zstd, client.compression());
+    }
+
+    #[tokio::test]
+    async fn test_socket_addrs() {
+        let client = ClientBuilder::default()
+            .socket_addrs(vec![
+                "127.0.0.1:4001".parse().unwrap(),
+                "[::1]:4001".parse().unwrap(),
+            ])
+            .build();
+        let peers: Vec<String> = client
+            .peer_status()
+            .into_iter()
+            .map(|status| status.peer)
+            .collect();
+        assert_eq!(vec!["127.0.0.1:4001", "[::1]:4001"], peers);
+    }
+
+    #[tokio::test]
+    async fn test_compression_threshold() {
+        let client = ClientBuilder::default().build();
+        assert_eq!(
+            Compression::Gzip,
+            client.compression_for(Compression::Gzip, 0)
+        );
+
+        let client = ClientBuilder::default().compression_threshold(1024).build();
+        assert_eq!(
+            Compression::None,
+            client.compression_for(Compression::Gzip, 1023)
+        );
+        assert_eq!(
+            Compression::Zstd,
+            client.compression_for(Compression::Zstd, 1024)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_channel_ready() {
+        let client = ClientBuilder::default().peers(mock_peers()).build();
+        let peer = "127.0.0.1:3001";
+        assert!(!client.channel_ready(peer));
+
+        client.record_result(peer, Ok(()));
+        assert!(client.channel_ready(peer));
+        assert!(!client.channel_ready("127.0.0.1:3002"));
+
+        let err = error::IllegalGrpcClientStateSnafu { err_msg: "down" }.build();
+        client.record_result(peer, Err(&err));
+        assert!(!client.channel_ready(peer));
+    }
+
+    #[tokio::test]
+    async fn test_failover_order() {
+        let client = ClientBuilder::default().build();
+        assert!(client.failover_order().is_empty());
+
+        let client = ClientBuilder::default().peers(mock_peers()).build();
+        client.record_result("127.0.0.1:3003", Ok(()));
+        let order = client.failover_order();
+        assert_eq!(
+            mock_peers().into_iter().collect::<HashSet<_>>(),
+            order.iter().cloned().collect::<HashSet<_>>()
+        );
+        // After the balanced pick, the ready peer comes before the others.
+        if order[0] != "127.0.0.1:3003" {
+            assert_eq!("127.0.0.1:3003", order[1]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_warmup() {
+        use futures::StreamExt;
+
+        // Nothing listens on these ports, so every probe fails fast.
+        let peers: Vec<String> = (1..=5).map(|port| format!("127.0.0.1:{port}")).collect();
+        let client = ClientBuilder::default().peers(&peers).build();
+
+        let health: Vec<_> = client.warmup(2, Duration::from_secs(5)).collect().await;
+        assert_eq!(
+            peers.into_iter().collect::<HashSet<_>>(),
+            health
+                .iter()
+                .map(|h| h.peer.clone())
+                .collect::<HashSet<_>>()
+        );
+        assert!(health.iter().all(|h| !h.healthy));
+    }
+
+    #[tokio::test]
+    async fn test_keep_warm() {
+        // Nothing listens on port 1, so warm pings mark the peer unhealthy.
+        let client = ClientBuilder::default()
+            .peers(vec!["127.0.0.1:1"])
+            .keep_warm_interval(Duration::from_millis(10))
+            .build();
+        let mut healthy = None;
+        for _ in 0..100 {
+            healthy = client.peer_status()[0].healthy;
+            if healthy.is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(Some(false), healthy);
+    }
+
+    #[test]
+    fn test_keep_warm_outside_runtime() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        // The channel manager needs a runtime, but the build doesn't.
+        let builder = runtime.block_on(async {
+            ClientBuilder::default()
+                .peers(vec!["127.0.0.1:1"])
+                .keep_warm_interval(Duration::from_millis(10))
+        });
+
+        let client = builder.build();
+        assert!(client.peer_status()[0].healthy.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_peer_status() {
+        let client = ClientBuilder::default().peers(mock_peers()).build();
+        client.record_result("127.0.0.1:3001", Ok(()));
+        let err = error::IllegalGrpcClientStateSnafu { err_msg: "down" }.build();
+        client.record_result("127.0.0.1:3002", Err(&err));
+
+        let status = client.peer_status();
+        assert_eq!(mock_peers().len(), status.len());
+
+        assert_eq!("127.0.0.1:3001", status[0].peer);
+        assert_eq!(Some(true), status[0].healthy);
+        assert!(status[0].last_success.is_some());
+        assert!(status[0].last_error.is_none());
+
+        assert_eq!(Some(false), status[1].healthy);
+        assert!(status[1].last_success.is_none());
+        assert_eq!(Some(err.to_string()), status[1].last_error);
+
+        assert!(status[2..].iter().all(|s| s.healthy.is_none()));
+    }
+
+    #[tokio::test]
+    async fn test_health_check_all_unreachable() {
+        // Nothing listens on port 1, so every probe fails fast.
+        let peers = vec!["127.0.0.1:1".to_string(), "127.0.0.1:2".to_string()];
+        let client = ClientBuilder::default().peers(&peers).build();
+
+        let health = client.health_check_all(Duration::from_secs(5)).await;
+
+        assert_eq!(
+            peers,
+            health.iter().map(|h| h.peer.clone()).collect::<Vec<_>>()
+        );
+        assert!(health.iter().all(|h| !h.healthy && h.latency.is_none()));
+        assert!(!client.channel_ready("127.0.0.1:1"));
+    }
+
+    #[tokio::test]
+    async fn test_health_check_all_invalid_peer() {
+        // The scheme is prepended, so no channel can be built for the peer.
+        let client = ClientBuilder::default().peers(vec!["http://test"]).build();
+
+        let health = client.health_check_all(Duration::from_secs(5)).await;
+
+        assert!(!health[0].healthy);
+        let status = client.peer_status();
+        assert_eq!(Some(false), status[0].healthy);
+        assert!(status[0].last_error.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit() {
+        let client = ClientBuilder::default().build();
+        assert_eq!(None, client.rate_limit());
+
+        let client = ClientBuilder::default().rate_limit(100).build();
+        assert_eq!(Some(100), client.rate_limit());
+        client.set_rate_limit(Some(10));
+        assert_eq!(Some(10), client.clone().rate_limit());
+        client.set_rate_limit(None);
+        assert_eq!(None, client.rate_limit());
+    }
 }
@@ -0,0 +1,250 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt;
+use std::time::Duration;
+
+use serde::Deserialize;
+use snafu::ensure;
+
+use crate::api::v1::auth_header::AuthScheme;
+use crate::api::v1::Basic;
+use crate::error::InvalidConfigSnafu;
+use crate::{ChannelConfig, ChannelManager, ClientBuilder, ClientTlsOption, Compression, Result};
+
+/// The client settings loadable from a config file, in any format supported
+/// by serde, e.g. TOML:
+///
+/// ```toml
+/// endpoints = ["127.0.0.1:4001"]
+/// compression = "zstd"
+/// timeout_ms = 5000
+/// username = "greptime_user"
+/// password = "greptime_pwd"
+///
+/// [tls]
+/// server_ca_cert_path = "/etc/greptime/ca.pem"
+/// ```
+///
+/// Build a client with [`ClientBuilder::from_config`], and authenticate the
+/// databases of the client with [`ClientConfig::auth_scheme`]. Every field is
+/// optional but `endpoints`, other settings are left to the builder's
+/// defaults.
+#[derive(Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClientConfig {
+    /// The peers to connect to, e.g. `127.0.0.1:4001`.
+    pub endpoints: Vec<String>,
+    /// `gzip`, `zstd` or `none`, see [`ClientBuilder::compression`].
+    pub compression: Option<String>,
+    /// See [`ChannelConfig::timeout`].
+    pub timeout_ms: Option<u64>,
+    /// See [`ChannelConfig::connect_timeout`].
+    pub connect_timeout_ms: Option<u64>,
+    /// Connect with TLS, see [`ChannelConfig::client_tls_config`].
+    pub tls: Option<ClientTlsOption>,
+    /// Authenticate with basic auth, set along `password`.
+    pub username: Option<String>,
+    /// The password of `username`.
+    pub password: Option<String>,
+    /// See [`ClientBuilder::client_id`].
+    pub client_id: Option<String>,
+    /// See [`ClientBuilder::rate_limit`].
+    pub rate_limit: Option<u32>,
+    /// See [`ClientBuilder::max_concurrent_requests`].
+    pub max_concurrent_requests: Option<usize>,
+}
+
+impl fmt::Debug for ClientConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientConfig")
+            .field("endpoints", &self.endpoints)
+            .field("compression", &self.compression)
+            .field("timeout_ms", &self.timeout_ms)
+            .field("connect_timeout_ms", &self.connect_timeout_ms)
+            .field("tls", &self.tls)
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| "<redacted>"))
+            .field("client_id", &self.client_id)
+            .field("rate_limit", &self.rate_limit)
+            .field("max_concurrent_requests", &self.max_concurrent_requests)
+            .finish()
+    }
+}
+
+impl ClientConfig {
+    /// The basic auth to set on databases with
+    /// [`Database::set_auth`](crate::Database::set_auth), if configured.
+    pub fn auth_scheme(&self) -> Option<AuthScheme> {
+        let (username, password) = (self.username.as_ref()?, self.password.as_ref()?);
+        Some(AuthScheme::Basic(Basic {
+            username: username.clone(),
+            password: password.clone(),
+        }))
+    }
+
+    fn validate(&self) -> Result<()> {
+        ensure!(
+            !self.endpoints.is_empty(),
+            InvalidConfigSnafu {
+                field: "endpoints",
+                msg: "at least one endpoint is required",
+            }
+        );
+        ensure!(
+            self.username.is_some() == self.password.is_some(),
+            InvalidConfigSnafu {
+                field: if self.username.is_some() {
+                    "password"
+                } else {
+                    "username"
+                },
+                msg: "username and password must be set together",
+            }
+        );
+        if let Some(tls) = &self.tls {
+            ensure!(
+                tls.client_cert_path.is_some() == tls.client_key_path.is_some(),
+                InvalidConfigSnafu {
+                    field: "tls",
+                    msg: "client_cert_path and client_key_path must be set together",
+                }
+            );
+        }
+        Ok(())
+    }
+}
+
+fn parse_compression(compression: &str) -> Result<Compression> {
+    match compression {
+        "gzip" => Ok(Compression::Gzip),
+        "zstd" => Ok(Compression::Zstd),
+        "none" => Ok(Compression::None),
+        _ => InvalidConfigSnafu {
+            field: "compression",
+            msg: format!("unknown compression {compression}, expected gzip, zstd or none"),
+        }
+        .fail(),
+    }
+}
+
+impl ClientBuilder {
+    /// A builder with the settings of `config`, which is validated first and
+    /// rejected with [`Error::InvalidConfig`](crate::Error::InvalidConfig)
+    /// naming the offending field.
+    ///
+    /// The builder remains the primary API: settings missing from
+    /// [`ClientConfig`] can still be chained before building. Must be called
+    /// within a tokio runtime, like [`ChannelManager::with_config`].
+    pub fn from_config(config: ClientConfig) -> Result<Self> {
+        config.validate()?;
+        let compression = config
+            .compression
+            .as_deref()
+            .map(parse_compression)
+            .transpose()?;
+
+        let mut channel_config = ChannelConfig::new();
+        if let Some(ms) = config.timeout_ms {
+            channel_config = channel_config.timeout(Duration::from_millis(ms));
+        }
+        if let Some(ms) = config.connect_timeout_ms {
+            channel_config = channel_config.connect_timeout(Duration::from_millis(ms));
+        }
+        let channel_manager = match config.tls {
+            Some(tls) => ChannelManager::with_tls_config(channel_config.client_tls_config(tls))?,
+            None => ChannelManager::with_config(channel_config),
+        };
+
+        let mut builder = ClientBuilder::default()
+            .channel_manager(channel_manager)
+            .peers(config.endpoints);
+        if let Some(compression) = compression {
+            builder = builder.compression(compression);
+        }
+        if let Some(client_id) = &config.client_id {
+            builder = builder.client_id(client_id);
+        }
+        if let Some(requests_per_sec) = config.rate_limit {
+            builder = builder.rate_limit(requests_per_sec);
+        }
+        if let Some(n) = config.max_concurrent_requests {
+            builder = builder.max_concurrent_requests(n);
+        }
+        Ok(builder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    fn parse(json: &str) -> ClientConfig {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_from_config() {
+        let config = parse(
+            r#"{
+                "endpoints": ["127.0.0.1:4001"],
+                "compression": "zstd",
+                "timeout_ms": 5000,
+                "username": "user",
+                "password": "pwd"
+            }"#,
+        );
+        assert!(matches!(
+            config.auth_scheme(),
+            Some(AuthScheme::Basic(Basic { username, .. })) if username == "user"
+        ));
+
+        let client = ClientBuilder::from_config(config).unwrap().build();
+        assert_eq!(Compression::Zstd, client.compression());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_config() {
+        let field = |json: &str| match ClientBuilder::from_config(parse(json)).err() {
+            Some(Error::InvalidConfig { field, .. }) => field,
+            other => panic!("unexpected error: {other:?}"),
+        };
+
+        assert_eq!("endpoints", field("{}"));
+        assert_eq!(
+            "compression",
+            field(r#"{"endpoints": ["a:4001"], "compression": "lz4"}"#)
+        );
+        assert_eq!(
+            "password",
+            field(r#"{"endpoints": ["a:4001"], "username": "user"}"#)
+        );
+        assert_eq!(
+            "tls",
+            field(r#"{"endpoints": ["a:4001"], "tls": {"client_cert_path": "cert.pem"}}"#)
+        );
+
+        assert!(serde_json::from_str::<ClientConfig>(r#"{"endpoint": []}"#).is_err());
+    }
+
+    #[test]
+    fn test_debug_redacts_password() {
+        let config = parse(r#"{"username": "user", "password": "secret_pwd"}"#);
+        let debug = format!("{config:?}");
+        assert!(debug.contains("user"));
+        assert!(debug.contains("<redacted>"));
+        assert!(!debug.contains("secret_pwd"));
+    }
+}
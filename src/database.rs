@@ -15,18 +15,41 @@
 use crate::api::v1::auth_header::AuthScheme;
 use crate::api::v1::greptime_request::Request;
 use crate::api::v1::{
-    greptime_response, AffectedRows, AuthHeader, DeleteRequests, GreptimeRequest, InsertRequest,
-    InsertRequests, RequestHeader, RowInsertRequests,
+    greptime_response, AffectedRows, AuthHeader, ColumnDataType, ColumnSchema, DeleteRequests,
+    GreptimeRequest, InsertRequest, InsertRequests, RequestHeader, Row, RowDeleteRequests,
+    RowInsertRequest, RowInsertRequests, SemanticType,
+};
+use crate::client::DatabaseClient;
+use crate::helpers::rows::{
+    check_future_timestamps, dedup_rows, validate_request, Keep, RowsBuilder,
 };
 use crate::stream_insert::StreamInserter;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use crate::error::{IllegalDatabaseResponseSnafu, InvalidAsciiSnafu};
-use crate::{Client, Result};
-use snafu::OptionExt;
-use tonic::metadata::MetadataValue;
+use crate::error::{
+    FailoverExhaustedSnafu, IllegalDatabaseResponseSnafu, IllegalGrpcClientStateSnafu,
+    InvalidAsciiSnafu, InvalidTimezoneSnafu, InvalidTtlSnafu, MessageTooLargeSnafu,
+    RowTooLargeSnafu, SchemaMismatchSnafu, TimeoutSnafu,
+};
+use crate::{Client, Compression, Error, Result, DEFAULT_CATALOG_NAME};
+use prost::Message;
+use snafu::{ensure, IntoError, OptionExt};
+use tonic::metadata::{Ascii, MetadataKey, MetadataMap, MetadataValue};
+use uuid::Uuid;
 
 const DEFAULT_STREAMING_INSERTER_BUFFER_SIZE: usize = 1024;
 
+const HINTS_KEY: &str = "x-greptime-hints";
+const APPEND_MODE_HINT: &str = "append_mode=true";
+const PHYSICAL_TABLE_HINT: &str = "physical_table";
+const TTL_HINT: &str = "ttl";
+const CLIENT_ID_KEY: &str = "x-greptime-client-id";
+const REQUEST_ID_KEY: &str = "x-greptime-request-id";
+
 /// The Client for GreptimeDB Database API.
 #[derive(Clone, Debug, Default)]
 pub struct Database {
@@ -36,6 +59,118 @@ pub struct Database {
 
     client: Client,
     auth_header: Option<AuthHeader>,
+    physical_table: Option<String>,
+    ttl: Option<Duration>,
+    max_future_skew: Option<Duration>,
+    client_dedup: Option<Keep>,
+    deduplicated_rows: Arc<AtomicU64>,
+    schemas: HashMap<String, Vec<ColumnSchema>>,
+    timezone: Option<String>,
+    deadline: Option<Instant>,
+}
+
+/// What [`Database::row_insert_batches`] does after a batch fails.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OnBatchError {
+    /// Stop at the failed batch, leaving the next ones unsent.
+    #[default]
+    Abort,
+    /// Go on with the next batches.
+    Continue,
+}
+
+/// The outcome of [`Database::row_insert_batches`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BatchSummary {
+    /// The rows written by the batches that succeeded.
+    pub affected_rows: u32,
+    /// The number of batches written.
+    pub succeeded: usize,
+    /// The number of batches that failed.
+    pub failed: usize,
+}
+
+/// A [`Database`] bound to one table and its schema, obtained with
+/// [`Database::table`], for single-table ingesters:
+///
+/// ```ignore
+/// let database = Arc::new(Database::new_with_dbname("public", client));
+/// let weather = database.table("weather", schema);
+/// weather.write_rows(rows).await?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct TableWriter {
+    database: Arc<Database>,
+    table: String,
+    rows: RowsBuilder,
+}
+
+impl TableWriter {
+    /// The table written to.
+    pub fn table(&self) -> &str {
+        &self.table
+    }
+
+    /// The schema of the rows written.
+    pub fn schema(&self) -> &[ColumnSchema] {
+        self.rows.schema()
+    }
+
+    /// Write `rows` to the table and get rows written. Fails with
+    /// [`Error::RowLengthMismatch`](crate::Error::RowLengthMismatch) if a row
+    /// doesn't have a value per column of the schema.
+    pub async fn write_rows(&self, rows: Vec<Row>) -> Result<u32> {
+        let rows = self.rows.build(rows)?;
+        let requests = RowInsertRequests {
+            inserts: vec![RowInsertRequest {
+                table_name: self.table.clone(),
+                rows: Some(rows),
+            }],
+        };
+        self.database.row_insert(requests).await
+    }
+}
+
+/// The outcome of concurrent writes, see [`aggregate_results`].
+#[derive(Debug, Default)]
+pub struct WriteSummary {
+    /// The rows written by the writes that succeeded.
+    pub affected_rows: u64,
+    pub succeeded: usize,
+    pub failed: usize,
+    /// The number of failed writes per [`Error::kind`], e.g. `"Server"`.
+    pub errors_by_kind: HashMap<&'static str, usize>,
+    /// The error of the first failed write, in the order of the results.
+    pub first_error: Option<Error>,
+}
+
+/// Sum up the results of concurrent writes, e.g. `row_insert` futures run
+/// with `join_all`, into a [`WriteSummary`].
+///
+/// Partial failures are reported in the summary. When every write failed,
+/// the first error is returned instead, so total failures propagate with `?`.
+/// No results make an empty summary.
+pub fn aggregate_results(results: Vec<Result<u32>>) -> Result<WriteSummary> {
+    let mut summary = WriteSummary::default();
+    for result in results {
+        match result {
+            Ok(rows) => {
+                summary.affected_rows += rows as u64;
+                summary.succeeded += 1;
+            }
+            Err(e) => {
+                summary.failed += 1;
+                *summary.errors_by_kind.entry(e.kind()).or_default() += 1;
+                summary.first_error.get_or_insert(e);
+            }
+        }
+    }
+    if summary.succeeded == 0 {
+        if let Some(e) = summary.first_error {
+            return Err(e);
+        }
+    }
+    Ok(summary)
 }
 
 impl Database {
@@ -51,6 +186,42 @@ impl Database {
             dbname: dbname.into(),
             client,
             auth_header: None,
+            physical_table: None,
+            ttl: None,
+            max_future_skew: None,
+            client_dedup: None,
+            deduplicated_rows: Arc::default(),
+            schemas: HashMap::new(),
+            timezone: None,
+            deadline: None,
+        }
+    }
+
+    /// Route the tables auto-created by this client's writes to the metric
+    /// engine physical table `name`.
+    ///
+    /// Sends the `physical_table=<name>` hint with every write, so new tables
+    /// are created as logical tables sharing that physical table. Like other
+    /// hints it only applies when a table is created, writes to existing
+    /// logical tables are routed by the server on its own.
+    pub fn with_physical_table(mut self, name: impl Into<String>) -> Self {
+        self.physical_table = Some(name.into());
+        self
+    }
+
+    /// Bind the database to `table` and its `schema`, to write rows without
+    /// building requests, see [`TableWriter`].
+    ///
+    /// The writer shares the database, creating one is nearly free.
+    pub fn table(
+        self: &Arc<Self>,
+        table: impl Into<String>,
+        schema: Vec<ColumnSchema>,
+    ) -> TableWriter {
+        TableWriter {
+            database: self.clone(),
+            table: table.into(),
+            rows: RowsBuilder::new(schema),
         }
     }
 
@@ -59,6 +230,113 @@ impl Database {
         &self.dbname
     }
 
+    /// Get the catalog part of the associated dbname, see [`parse_dbname`]
+    pub fn catalog(&self) -> &str {
+        parse_dbname(&self.dbname).0
+    }
+
+    /// Get the schema part of the associated dbname, see [`parse_dbname`]
+    pub fn schema(&self) -> &str {
+        parse_dbname(&self.dbname).1
+    }
+
+    /// Expire the rows of tables auto-created by this client's writes after
+    /// `ttl`.
+    ///
+    /// Sends the `ttl=<seconds>s` hint with every write, unary and streaming.
+    /// GreptimeDB has no per-row TTL: the hint sets the TTL option of tables
+    /// created by the write, existing tables keep their own. The server
+    /// accepts a granularity of seconds, so `ttl` must be a non-zero whole
+    /// number of seconds, or [`Error::InvalidTtl`] is returned.
+    pub fn with_ttl(mut self, ttl: Duration) -> Result<Self> {
+        ensure!(
+            ttl.as_secs() > 0 && ttl.subsec_nanos() == 0,
+            InvalidTtlSnafu { ttl }
+        );
+        self.ttl = Some(ttl);
+        Ok(self)
+    }
+
+    /// Reject unary row inserts with a time index value more than `max_skew`
+    /// ahead of the local clock, see
+    /// [`check_future_timestamps`](crate::helpers::rows::check_future_timestamps).
+    ///
+    /// A misconfigured producer clock then fails with
+    /// [`Error::FutureTimestamp`](crate::Error::FutureTimestamp) instead of
+    /// writing rows far in the future. Late data with past timestamps is
+    /// still accepted.
+    pub fn reject_future_timestamps(mut self, max_skew: Duration) -> Self {
+        self.max_future_skew = Some(max_skew);
+        self
+    }
+
+    /// Remove duplicate rows, with the same tags and timestamp, from unary row
+    /// inserts before sending them, keeping the first or last of them, see
+    /// [`dedup_rows`](crate::helpers::rows::dedup_rows).
+    ///
+    /// Off by default: the server deduplicates on its own and is
+    /// authoritative, this only saves payload when the source has known
+    /// duplicates. See [`Database::deduplicated_rows`] for the rows removed.
+    pub fn client_dedup(mut self, keep: Keep) -> Self {
+        self.client_dedup = Some(keep);
+        self
+    }
+
+    /// The number of rows removed by [client dedup](Database::client_dedup)
+    /// so far, shared by the clones of this database.
+    pub fn deduplicated_rows(&self) -> u64 {
+        self.deduplicated_rows.load(Ordering::Relaxed)
+    }
+
+    /// Register the schema of `table`, to validate every unary row insert to
+    /// it against before sending.
+    ///
+    /// A request may cover a subset of the columns, but each of them must be
+    /// in `schema` with the same datatype and semantic type, or the write
+    /// fails with [`Error::SchemaMismatch`](crate::Error::SchemaMismatch)
+    /// naming the offending column. Rows are then checked like
+    /// [`validate_request`](crate::helpers::rows::validate_request) does,
+    /// including nulls in non-nullable columns. Registering a table again
+    /// replaces its schema.
+    pub fn register_schema(mut self, table: impl Into<String>, schema: Vec<ColumnSchema>) -> Self {
+        self.schemas.insert(table.into(), schema);
+        self
+    }
+
+    /// Set the timezone of the server-side query context of every request,
+    /// unary and streaming, e.g. `Asia/Shanghai`, `UTC` or `+08:00`.
+    ///
+    /// It changes how the server interprets timezone-dependent values, like
+    /// strings converted to timestamps or datetimes. Timestamp and datetime
+    /// values are epochs and are not shifted: the chrono helpers such as
+    /// [`datetime_value_tz`](crate::helpers::values::datetime_value_tz)
+    /// already normalize to UTC. A timezone that is neither a `±HH:MM` offset
+    /// nor shaped like a named zone fails with
+    /// [`Error::InvalidTimezone`](crate::Error::InvalidTimezone). The server
+    /// rejects names it doesn't know.
+    pub fn with_timezone(mut self, timezone: &str) -> Result<Self> {
+        ensure!(
+            is_valid_timezone(timezone),
+            InvalidTimezoneSnafu { timezone }
+        );
+        self.timezone = Some(timezone.to_string());
+        Ok(self)
+    }
+
+    /// Bound unary requests by `deadline`, e.g. inherited from the incoming
+    /// request of a server, for end-to-end deadline propagation.
+    ///
+    /// The time left is sent to the server as the gRPC timeout, and requests
+    /// still waiting at the deadline, for the client's concurrency or rate
+    /// limit or for their response, fail with
+    /// [`Error::Timeout`](crate::Error::Timeout), as do requests made once it
+    /// passed. Set it on a clone scoped to the incoming request:
+    /// `database.clone().with_deadline(deadline)`.
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
     /// Update dbname of this client
     pub fn set_dbname(&mut self, dbname: impl Into<String>) {
         self.dbname = dbname.into();
@@ -79,6 +357,13 @@ impl Database {
     }
 
     /// Write Row based insert requests to GreptimeDB and get rows written
+    ///
+    /// The schema of each request may cover only a subset of the table's
+    /// columns: the server fills the missing ones with nulls, or their
+    /// defaults, and adds columns it doesn't know yet. Each schema must still
+    /// include the time index column, and every column must keep the semantic
+    /// type and datatype it has in the table, in particular all tags of the
+    /// table are part of the primary key, so omitting one writes null for it.
     pub async fn row_insert(&self, requests: RowInsertRequests) -> Result<u32> {
         self.handle(Request::RowInserts(requests), None).await
     }
@@ -92,6 +377,130 @@ impl Database {
         self.handle(Request::RowInserts(requests), Some(hint)).await
     }
 
+    /// Write Row based insert requests to GreptimeDB and get rows written,
+    /// sending them with `compression` instead of the client's
+    ///
+    /// Useful for mixed traffic, e.g. to compress large bulk writes while small
+    /// ones skip the CPU cost.
+    pub async fn row_insert_with_compression(
+        &self,
+        requests: RowInsertRequests,
+        compression: Compression,
+    ) -> Result<u32> {
+        self.handle_with(Request::RowInserts(requests), None, compression, None)
+            .await
+    }
+
+    /// Write `batches` of Row based insert requests one after another, for
+    /// best-effort bulk loads.
+    ///
+    /// A failed batch doesn't fail the whole load: `on_error` is called with
+    /// its index in `batches` and its error, e.g. to route its rows to a
+    /// dead-letter sink, then the load stops or goes on as set by `policy`.
+    /// Batches left unsent on [`OnBatchError::Abort`] are counted in neither
+    /// `succeeded` nor `failed` of the returned summary.
+    pub async fn row_insert_batches<I, F>(
+        &self,
+        batches: I,
+        policy: OnBatchError,
+        mut on_error: F,
+    ) -> BatchSummary
+    where
+        I: IntoIterator<Item = RowInsertRequests>,
+        F: FnMut(usize, &Error),
+    {
+        let mut summary = BatchSummary::default();
+        for (i, batch) in batches.into_iter().enumerate() {
+            match self.row_insert(batch).await {
+                Ok(rows) => {
+                    summary.affected_rows += rows;
+                    summary.succeeded += 1;
+                }
+                Err(e) => {
+                    summary.failed += 1;
+                    on_error(i, &e);
+                    if policy == OnBatchError::Abort {
+                        break;
+                    }
+                }
+            }
+        }
+        summary
+    }
+
+    /// Write Row based insert requests to GreptimeDB and get rows written,
+    /// failing over to the other peers while they can't serve it.
+    ///
+    /// The request is tried on each configured peer at most once, see
+    /// [`Error::is_peer_failure`](crate::Error::is_peer_failure) for the
+    /// errors moving on to the next one, so a partial outage is masked as long
+    /// as one peer is up. Other errors are returned right away. Once every peer
+    /// failed, [`Error::FailoverExhausted`](crate::Error::FailoverExhausted)
+    /// lists them with the last error.
+    pub async fn row_insert_with_failover(&self, requests: RowInsertRequests) -> Result<u32> {
+        let peers = self.client.failover_order();
+        ensure!(
+            !peers.is_empty(),
+            IllegalGrpcClientStateSnafu {
+                err_msg: "No available peer found",
+            }
+        );
+
+        // Dedup and validate once, only the send is repeated per peer.
+        let Some(request) = self.prepare(Request::RowInserts(requests))? else {
+            return Ok(0);
+        };
+        let mut attempted = Vec::with_capacity(peers.len());
+        let mut last_error = None;
+        for peer in peers {
+            match self
+                .send(
+                    request.clone(),
+                    None,
+                    self.client.compression(),
+                    Some(&peer),
+                )
+                .await
+            {
+                Err(e) if e.is_peer_failure() => {
+                    attempted.push(peer);
+                    last_error = Some(e);
+                }
+                result => return result,
+            }
+        }
+        let last_error = last_error.expect("every peer failed");
+        Err(FailoverExhaustedSnafu { attempted }.into_error(Box::new(last_error)))
+    }
+
+    /// Write Row based insert requests to the given `peer` and get rows
+    /// written, bypassing the load balancer
+    ///
+    /// An escape hatch for targeted tests or affinity strategies. `peer` must
+    /// be one of the client's configured peers, or
+    /// [`Error::UnknownPeer`](crate::Error::UnknownPeer) is returned.
+    pub async fn row_insert_to_peer(&self, peer: &str, requests: RowInsertRequests) -> Result<u32> {
+        self.handle_with(
+            Request::RowInserts(requests),
+            None,
+            self.client.compression(),
+            Some(peer),
+        )
+        .await
+    }
+
+    /// Write Row based insert requests to append-only tables and get rows
+    /// written
+    ///
+    /// Sends the `append_mode=true` hint, so tables auto-created by this write
+    /// skip deduplication and merging of rows, trading them for higher
+    /// throughput. This suits logs and events where no dedup is wanted. The
+    /// hint requires server support and only applies when the table is
+    /// created, existing tables keep their mode.
+    pub async fn append_insert(&self, requests: RowInsertRequests) -> Result<u32> {
+        self.row_insert_with_hint(requests, APPEND_MODE_HINT).await
+    }
+
     /// Initialise a streaming insert handle, using default buffer size `1024`
     pub fn default_streaming_inserter(&self) -> Result<StreamInserter> {
         self.streaming_inserter(DEFAULT_STREAMING_INSERTER_BUFFER_SIZE, None)
@@ -103,43 +512,190 @@ impl Database {
         channel_size: usize,
         hint: Option<&str>,
     ) -> Result<StreamInserter> {
-        let client = self.client.make_database_client()?.inner;
-        let hint = hint
-            .map(|value| {
-                MetadataValue::try_from(value).map_err(|_| InvalidAsciiSnafu { value }.build())
-            })
-            .transpose()?;
+        self.streaming_inserter_with_compression(channel_size, hint, self.client.compression())
+    }
 
-        StreamInserter::new(
+    /// Initialise a streaming insert handle, using custom buffer size and
+    /// hint, and sending with `compression` instead of the client's
+    pub fn streaming_inserter_with_compression(
+        &self,
+        channel_size: usize,
+        hint: Option<&str>,
+        compression: Compression,
+    ) -> Result<StreamInserter> {
+        self.new_streaming_inserter(channel_size, hint, compression, None)
+    }
+
+    /// Initialise a streaming insert handle to the given `peer`, bypassing the
+    /// load balancer like [`Database::row_insert_to_peer`]
+    pub fn streaming_inserter_to_peer(
+        &self,
+        channel_size: usize,
+        hint: Option<&str>,
+        peer: &str,
+    ) -> Result<StreamInserter> {
+        self.new_streaming_inserter(channel_size, hint, self.client.compression(), Some(peer))
+    }
+
+    fn new_streaming_inserter(
+        &self,
+        channel_size: usize,
+        hint: Option<&str>,
+        compression: Compression,
+        peer: Option<&str>,
+    ) -> Result<StreamInserter> {
+        let client = match peer {
+            Some(peer) => self
+                .client
+                .make_database_client_to_peer(peer, compression)?,
+            None => self.client.make_database_client(compression)?,
+        }
+        .inner;
+        let metadata = self.request_metadata(hint)?;
+
+        let inserter = StreamInserter::new(
             client,
             self.dbname().to_string(),
             self.auth_header.clone(),
             channel_size,
-            hint,
-        )
+            metadata,
+            self.client.rate_limiter().clone(),
+            self.client.max_encoding_message_size(),
+        )?;
+        Ok(inserter
+            .skip_empty_requests(!self.client.send_empty_requests())
+            .timezone(self.timezone.clone()))
     }
 
     /// Issue a delete to database
+    ///
+    /// The server reports a single total of deleted rows across all tables in
+    /// `request`, per-table counts are not available.
     pub async fn delete(&self, request: DeleteRequests) -> Result<u32> {
         self.handle(Request::Deletes(request), None).await
     }
 
+    /// Issue a Row based delete to database and get the total rows deleted
+    /// across all tables.
+    ///
+    /// Use [`row_delete_requests`](crate::helpers::delete::row_delete_requests)
+    /// to build a multi-table request.
+    pub async fn row_delete(&self, requests: RowDeleteRequests) -> Result<u32> {
+        self.handle(Request::RowDeletes(requests), None).await
+    }
+
+    /// Measure the round-trip time to a peer picked like for writes, by
+    /// sending it a health check.
+    ///
+    /// The latency is also reported to the load balancer, see
+    /// [`LoadBalance::record_latency`](crate::load_balance::LoadBalance::record_latency).
+    pub async fn ping(&self) -> Result<Duration> {
+        self.client.ping().await
+    }
+
+    /// Send an arbitrary request to the database and get rows affected.
+    ///
+    /// This is a lower-level escape hatch for request kinds the typed API
+    /// doesn't cover yet. The standard header, carrying dbname and
+    /// authentication, is still attached. Prefer the typed methods whenever one
+    /// exists for your request.
+    pub async fn execute_raw(&self, request: Request) -> Result<u32> {
+        self.handle(request, None).await
+    }
+
     async fn handle(&self, request: Request, hint: Option<&str>) -> Result<u32> {
-        let mut client = self.client.make_database_client()?.inner;
+        self.handle_with(request, hint, self.client.compression(), None)
+            .await
+    }
+
+    /// Send `request` with `compression` to `peer`, or to the peer picked by
+    /// the load balancer.
+    async fn handle_with(
+        &self,
+        request: Request,
+        hint: Option<&str>,
+        compression: Compression,
+        peer: Option<&str>,
+    ) -> Result<u32> {
+        match self.prepare(request)? {
+            Some(request) => self.send(request, hint, compression, peer).await,
+            None => Ok(0),
+        }
+    }
+
+    /// Dedup and validate `request`, and build the RPC request to send, or
+    /// `None` for a row insert without rows not to be sent.
+    fn prepare(&self, mut request: Request) -> Result<Option<GreptimeRequest>> {
+        if let (Some(keep), Request::RowInserts(requests)) = (self.client_dedup, &mut request) {
+            for rows in requests
+                .inserts
+                .iter_mut()
+                .filter_map(|insert| insert.rows.as_mut())
+            {
+                let removed = dedup_rows(rows, keep);
+                self.deduplicated_rows
+                    .fetch_add(removed as u64, Ordering::Relaxed);
+            }
+        }
+        if let Request::RowInserts(requests) = &request {
+            if !self.client.send_empty_requests() && is_empty(requests) {
+                return Ok(None);
+            }
+            self.check_registered_schemas(requests)?;
+        }
+        if let (Some(max_skew), Request::RowInserts(requests)) = (self.max_future_skew, &request) {
+            for rows in requests
+                .inserts
+                .iter()
+                .filter_map(|insert| insert.rows.as_ref())
+            {
+                check_future_timestamps(rows, max_skew)?;
+            }
+        }
         let request = self.to_rpc_request(request);
+        check_message_size(&request, self.client.max_encoding_message_size())?;
+        Ok(Some(request))
+    }
+
+    /// Send the [prepared](Database::prepare) `request` with `compression` to
+    /// `peer`, or to the peer picked by the load balancer.
+    async fn send(
+        &self,
+        request: GreptimeRequest,
+        hint: Option<&str>,
+        compression: Compression,
+        peer: Option<&str>,
+    ) -> Result<u32> {
+        let compression = self
+            .client
+            .compression_for(compression, request.encoded_len());
+        let DatabaseClient {
+            inner: mut client,
+            peer,
+        } = match peer {
+            Some(peer) => self
+                .client
+                .make_database_client_to_peer(peer, compression)?,
+            None => self.client.make_database_client(compression)?,
+        };
         let mut request = tonic::Request::new(request);
-        if let Some(hint) = hint {
-            let hint = MetadataValue::try_from(hint).map_err(|_| {
-                InvalidAsciiSnafu {
-                    value: hint.to_string(),
-                }
-                .build()
-            })?;
-            request.metadata_mut().insert("x-greptime-hints", hint);
+        *request.metadata_mut() = self.request_metadata(hint)?;
+        let request_id = insert_request_id(&mut request)?;
+        let _in_flight = self
+            .before_deadline(self.client.concurrency_limiter().acquire())
+            .await??;
+        self.before_deadline(self.client.rate_limiter().acquire())
+            .await?;
+        if let Some(deadline) = self.deadline {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            ensure!(!timeout.is_zero(), TimeoutSnafu { timeout });
+            request.set_timeout(timeout);
         }
-        let response = client
-            .handle(request)
-            .await?
+        let response = self.before_deadline(client.handle(request)).await?;
+        let response = response.map_err(|e| Error::from(e).with_request_id(&request_id));
+        self.client
+            .record_result(&peer, response.as_ref().map(|_| ()));
+        let response = response?
             .into_inner()
             .response
             .context(IllegalDatabaseResponseSnafu {
@@ -149,12 +705,98 @@ impl Database {
         Ok(value)
     }
 
+    /// Wait for `future`, failing with [`Error::Timeout`] if the
+    /// [deadline](Database::with_deadline) passes first.
+    async fn before_deadline<F: Future>(&self, future: F) -> Result<F::Output> {
+        let Some(deadline) = self.deadline else {
+            return Ok(future.await);
+        };
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        tokio::time::timeout_at(deadline.into(), future)
+            .await
+            .ok()
+            .context(TimeoutSnafu { timeout })
+    }
+
+    /// Build the gRPC metadata shared by unary and streaming requests.
+    fn request_metadata(&self, hint: Option<&str>) -> Result<MetadataMap> {
+        let mut metadata = MetadataMap::new();
+        let mut hints = Vec::new();
+        if let Some(hint) = hint {
+            hints.push(hint.to_string());
+        }
+        if let Some(name) = &self.physical_table {
+            hints.push(format!("{PHYSICAL_TABLE_HINT}={name}"));
+        }
+        if let Some(ttl) = self.ttl {
+            hints.push(format!("{TTL_HINT}={}s", ttl.as_secs()));
+        }
+        if !hints.is_empty() {
+            metadata.insert(HINTS_KEY, to_metadata_value(&hints.join(","))?);
+        }
+        if let Some(client_id) = self.client.client_id() {
+            metadata.insert(CLIENT_ID_KEY, to_metadata_value(client_id)?);
+        }
+        for (key, value) in self.client.trace_context() {
+            let key = MetadataKey::<Ascii>::from_bytes(key.as_bytes())
+                .map_err(|_| InvalidAsciiSnafu { value: &key }.build())?;
+            metadata.insert(key, to_metadata_value(&value)?);
+        }
+        Ok(metadata)
+    }
+
+    fn check_registered_schemas(&self, requests: &RowInsertRequests) -> Result<()> {
+        for insert in &requests.inserts {
+            let (Some(registered), Some(rows)) =
+                (self.schemas.get(&insert.table_name), &insert.rows)
+            else {
+                continue;
+            };
+            for column in &rows.schema {
+                let mismatch = match registered
+                    .iter()
+                    .find(|registered| registered.column_name == column.column_name)
+                {
+                    None => Some(format!(
+                        "column {} is not in the registered schema",
+                        column.column_name
+                    )),
+                    Some(registered) if registered.datatype != column.datatype => Some(format!(
+                        "column {} has datatype {:?}, expect {:?}",
+                        column.column_name,
+                        ColumnDataType::try_from(column.datatype).unwrap_or_default(),
+                        ColumnDataType::try_from(registered.datatype).unwrap_or_default()
+                    )),
+                    Some(registered) if registered.semantic_type != column.semantic_type => {
+                        Some(format!(
+                            "column {} has semantic type {:?}, expect {:?}",
+                            column.column_name,
+                            SemanticType::try_from(column.semantic_type).unwrap_or_default(),
+                            SemanticType::try_from(registered.semantic_type).unwrap_or_default()
+                        ))
+                    }
+                    Some(_) => None,
+                };
+                if let Some(msg) = mismatch {
+                    return SchemaMismatchSnafu {
+                        table: &insert.table_name,
+                        msg,
+                    }
+                    .fail();
+                }
+            }
+            validate_request(insert)?;
+        }
+        Ok(())
+    }
+
     #[inline]
     fn to_rpc_request(&self, request: Request) -> GreptimeRequest {
         GreptimeRequest {
             header: Some(RequestHeader {
                 authorization: self.auth_header.clone(),
                 dbname: self.dbname.clone(),
+                timezone: self.timezone.clone().unwrap_or_default(),
                 ..Default::default()
             }),
             request: Some(request),
@@ -162,5 +804,540 @@ impl Database {
     }
 }
 
+/// Split a dbname into its `(catalog, schema)` parts, following the server's
+/// rules.
+///
+/// A dbname of the form `<catalog>-<schema>` is split at the first `-`. Any
+/// other dbname is a schema in the [`DEFAULT_CATALOG_NAME`] catalog.
+pub fn parse_dbname(dbname: &str) -> (&str, &str) {
+    match dbname.split_once('-') {
+        Some((catalog, schema)) => (catalog, schema),
+        None => (DEFAULT_CATALOG_NAME, dbname),
+    }
+}
+
+/// Generate a request id and attach it to the request metadata, so the
+/// request can be correlated with server-side logs.
+pub(crate) fn insert_request_id<T>(request: &mut tonic::Request<T>) -> Result<String> {
+    let request_id = Uuid::new_v4().to_string();
+    request
+        .metadata_mut()
+        .insert(REQUEST_ID_KEY, to_metadata_value(&request_id)?);
+    Ok(request_id)
+}
+
+/// Whether `timezone` is a `±HH:MM` offset or shaped like a named zone, e.g.
+/// `UTC` or `America/New_York`.
+fn is_valid_timezone(timezone: &str) -> bool {
+    let offset = timezone.as_bytes();
+    if let [b'+' | b'-', h1, h2, b':', m1, m2] = offset {
+        return [h1, h2, m1, m2].iter().all(|b| b.is_ascii_digit())
+            && (*m1 - b'0') < 6
+            && (*h1 - b'0') * 10 + (*h2 - b'0') <= 14;
+    }
+    timezone.starts_with(|c: char| c.is_ascii_alphabetic())
+        && timezone
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '/' | '_' | '-' | '+'))
+}
+
+/// Whether `requests` have no row to write.
+pub(crate) fn is_empty(requests: &RowInsertRequests) -> bool {
+    requests
+        .inserts
+        .iter()
+        .all(|insert| insert.rows.as_ref().is_none_or(|rows| rows.rows.is_empty()))
+}
+
+/// Reject `request` if it encodes to more than `limit` bytes. Row inserts are
+/// rejected with [`Error::RowTooLarge`] if a single row is over `limit`, since
+/// splitting by rows can't help then.
+pub(crate) fn check_message_size(request: &GreptimeRequest, limit: Option<usize>) -> Result<()> {
+    let Some(limit) = limit else {
+        return Ok(());
+    };
+    let size = request.encoded_len();
+    if size <= limit {
+        return Ok(());
+    }
+    if let Some(Request::RowInserts(requests)) = &request.request {
+        for insert in &requests.inserts {
+            let rows = insert.rows.iter().flat_map(|rows| &rows.rows);
+            for (row_index, row) in rows.enumerate() {
+                let size = row.encoded_len();
+                ensure!(
+                    size <= limit,
+                    RowTooLargeSnafu {
+                        table: &insert.table_name,
+                        row_index,
+                        size,
+                        limit,
+                    }
+                );
+            }
+        }
+    }
+    MessageTooLargeSnafu { size, limit }.fail()
+}
+
+fn to_metadata_value(value: &str) -> Result<MetadataValue<Ascii>> {
+    MetadataValue::try_from(value).map_err(|_| {
+        InvalidAsciiSnafu {
+            value: value.to_string(),
+        }
+        .build()
+    })
+}
+
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    use super::{
+        aggregate_results, check_message_size, parse_dbname, Database, OnBatchError, HINTS_KEY,
+    };
+    use crate::api::v1::greptime_request::Request;
+    use crate::api::v1::{
+        ColumnDataType, ColumnSchema, GreptimeRequest, Row, RowInsertRequest, RowInsertRequests,
+        Rows, Value,
+    };
+    use crate::helpers::schema::{field, tag, timestamp};
+    use crate::helpers::values::{f64_value, i64_value, timestamp_millisecond_value};
+    use crate::{ClientBuilder, Error};
+
+    #[test]
+    fn test_aggregate_results() {
+        let unknown_column = || crate::error::UnknownColumnSnafu { column: "c" }.build();
+        let summary = aggregate_results(vec![
+            Ok(10),
+            Err(Error::from(tonic::Status::unavailable("down"))),
+            Ok(5),
+            Err(unknown_column()),
+            Err(unknown_column()),
+        ])
+        .unwrap();
+        assert_eq!(15, summary.affected_rows);
+        assert_eq!((2, 3), (summary.succeeded, summary.failed));
+        assert_eq!(1, summary.errors_by_kind["Server"]);
+        assert_eq!(2, summary.errors_by_kind["UnknownColumn"]);
+        assert!(matches!(summary.first_error, Some(Error::Server { .. })));
+
+        let err = aggregate_results(vec![Err(unknown_column())]).unwrap_err();
+        assert!(matches!(err, Error::UnknownColumn { .. }));
+        assert_eq!(0, aggregate_results(Vec::new()).unwrap().succeeded);
+    }
+
+    #[tokio::test]
+    async fn test_table_writer() {
+        let database = std::sync::Arc::new(Database::new_with_dbname(
+            "public",
+            ClientBuilder::default().build(),
+        ));
+        let schema = vec![timestamp("ts", ColumnDataType::TimestampMillisecond)];
+        let writer = database.table("metrics", schema.clone());
+        assert_eq!("metrics", writer.table());
+        assert_eq!(schema, writer.schema());
+
+        let err = writer
+            .write_rows(vec![Row {
+                values: vec![timestamp_millisecond_value(1), f64_value(1.0)],
+            }])
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::RowLengthMismatch { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_results_reach_load_balancer() {
+        use std::sync::atomic::Ordering;
+
+        use crate::load_balance::{Counting, Loadbalancer};
+
+        let counting = Counting::default();
+        // Nothing listens on port 1, so every request fails.
+        let client = ClientBuilder::default()
+            .peers(vec!["127.0.0.1:1"])
+            .load_balance(Loadbalancer::from(counting.clone()))
+            .build();
+        let database = Database::new_with_dbname("public", client);
+        let requests = RowInsertRequests {
+            inserts: vec![RowInsertRequest {
+                table_name: "metrics".to_string(),
+                rows: Some(Rows {
+                    schema: vec![timestamp("ts", ColumnDataType::TimestampMillisecond)],
+                    rows: vec![Row {
+                        values: vec![timestamp_millisecond_value(1)],
+                    }],
+                }),
+            }],
+        };
+
+        assert!(database.row_insert(requests).await.is_err());
+        assert_eq!(1, counting.results.load(Ordering::Relaxed));
+
+        // A failed ping is a result, but measures no latency.
+        assert!(database.ping().await.is_err());
+        assert_eq!(2, counting.results.load(Ordering::Relaxed));
+        assert_eq!(0, counting.latencies.load(Ordering::Relaxed));
+    }
+
+    #[tokio::test]
+    async fn test_failover_dedups_once() {
+        // Nothing listens on ports 1 and 2, so both peers fail.
+        let client = ClientBuilder::default()
+            .peers(vec!["127.0.0.1:1", "127.0.0.1:2"])
+            .build();
+        let database = Database::new_with_dbname("public", client)
+            .client_dedup(crate::helpers::rows::Keep::Last);
+        let row = Row {
+            values: vec![timestamp_millisecond_value(1)],
+        };
+        let requests = RowInsertRequests {
+            inserts: vec![RowInsertRequest {
+                table_name: "metrics".to_string(),
+                rows: Some(Rows {
+                    schema: vec![timestamp("ts", ColumnDataType::TimestampMillisecond)],
+                    rows: vec![row.clone(), row],
+                }),
+            }],
+        };
+
+        let err = database
+            .row_insert_with_failover(requests)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, Error::FailoverExhausted { ref attempted, .. } if attempted.len() == 2)
+        );
+        assert_eq!(1, database.deduplicated_rows());
+    }
+
+    #[tokio::test]
+    async fn test_deadline_while_waiting_for_permit() {
+        let client = ClientBuilder::default()
+            .peers(vec!["127.0.0.1:1"])
+            .max_concurrent_requests(1)
+            .build();
+        let database = Database::new_with_dbname("public", client)
+            .with_deadline(std::time::Instant::now() + Duration::from_millis(50));
+        let _held = database
+            .client
+            .concurrency_limiter()
+            .acquire()
+            .await
+            .unwrap();
+        let requests = RowInsertRequests {
+            inserts: vec![RowInsertRequest {
+                table_name: "metrics".to_string(),
+                rows: Some(Rows {
+                    schema: vec![timestamp("ts", ColumnDataType::TimestampMillisecond)],
+                    rows: vec![Row {
+                        values: vec![timestamp_millisecond_value(1)],
+                    }],
+                }),
+            }],
+        };
+
+        let result = tokio::time::timeout(Duration::from_secs(5), database.row_insert(requests))
+            .await
+            .expect("the deadline bounds the wait for a permit");
+        assert!(matches!(result, Err(Error::Timeout { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_past_deadline() {
+        let client = ClientBuilder::default()
+            .peers(vec!["127.0.0.1:4001"])
+            .build();
+        let database = Database::new_with_dbname("public", client)
+            .with_deadline(std::time::Instant::now() - Duration::from_secs(1));
+        let requests = RowInsertRequests {
+            inserts: vec![RowInsertRequest {
+                table_name: "metrics".to_string(),
+                rows: Some(Rows {
+                    schema: vec![timestamp("ts", ColumnDataType::TimestampMillisecond)],
+                    rows: vec![Row {
+                        values: vec![timestamp_millisecond_value(1)],
+                    }],
+                }),
+            }],
+        };
+        let err = database.row_insert(requests).await.unwrap_err();
+        assert!(matches!(err, Error::Timeout { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_streaming_inserter_to_unknown_peer() {
+        let client = ClientBuilder::default()
+            .peers(vec!["127.0.0.1:4001"])
+            .build();
+        let database = Database::new_with_dbname("public", client);
+
+        let result = database.streaming_inserter_to_peer(16, None, "127.0.0.1:4002");
+        assert!(matches!(result, Err(Error::UnknownPeer { .. })));
+        assert!(database
+            .streaming_inserter_to_peer(16, None, "127.0.0.1:4001")
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_row_insert_batches() {
+        // Without peers every batch fails before anything is sent.
+        let database = Database::new_with_dbname("public", ClientBuilder::default().build());
+        let batches = || (0..3).map(|_| RowInsertRequests::default());
+
+        let mut failed = Vec::new();
+        let summary = database
+            .row_insert_batches(batches(), OnBatchError::Continue, |i, _| failed.push(i))
+            .await;
+        assert_eq!(vec![0, 1, 2], failed);
+        assert_eq!(
+            (0, 0, 3),
+            (summary.affected_rows, summary.succeeded, summary.failed)
+        );
+
+        let mut failed = Vec::new();
+        let summary = database
+            .row_insert_batches(batches(), OnBatchError::Abort, |i, _| failed.push(i))
+            .await;
+        assert_eq!(vec![0], failed);
+        assert_eq!(1, summary.failed);
+    }
+
+    #[tokio::test]
+    async fn test_register_schema() {
+        let schema = vec![
+            timestamp("ts", ColumnDataType::TimestampMillisecond),
+            field("value", ColumnDataType::Float64),
+        ];
+        let database = Database::new_with_dbname("public", ClientBuilder::default().build())
+            .register_schema("metrics", schema.clone());
+
+        let requests = |schema: Vec<ColumnSchema>, values: Vec<Value>| RowInsertRequests {
+            inserts: vec![RowInsertRequest {
+                table_name: "metrics".to_string(),
+                rows: Some(Rows {
+                    schema,
+                    rows: vec![Row { values }],
+                }),
+            }],
+        };
+
+        database
+            .check_registered_schemas(&requests(
+                schema.clone(),
+                vec![timestamp_millisecond_value(1), f64_value(1.0)],
+            ))
+            .unwrap();
+        // A subset of the columns is fine.
+        database
+            .check_registered_schemas(&requests(
+                schema[..1].to_vec(),
+                vec![timestamp_millisecond_value(1)],
+            ))
+            .unwrap();
+
+        let err = database
+            .check_registered_schemas(&requests(
+                vec![schema[0].clone(), field("value", ColumnDataType::Int64)],
+                vec![timestamp_millisecond_value(1), i64_value(1)],
+            ))
+            .unwrap_err();
+        assert!(matches!(err, Error::SchemaMismatch { ref msg, .. } if msg.contains("value")));
+
+        let err = database
+            .check_registered_schemas(&requests(
+                vec![schema[0].clone(), tag("value", ColumnDataType::Float64)],
+                vec![timestamp_millisecond_value(1), f64_value(1.0)],
+            ))
+            .unwrap_err();
+        assert!(matches!(err, Error::SchemaMismatch { .. }));
+
+        let err = database
+            .check_registered_schemas(&requests(
+                schema.clone(),
+                vec![timestamp_millisecond_value(1), i64_value(1)],
+            ))
+            .unwrap_err();
+        assert!(matches!(err, Error::ValueTypeMismatch { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_skip_empty_requests() {
+        // Without peers, anything actually sent fails.
+        let database = Database::new_with_dbname("public", ClientBuilder::default().build());
+        let empty = RowInsertRequests {
+            inserts: vec![RowInsertRequest {
+                table_name: "metrics".to_string(),
+                rows: None,
+            }],
+        };
+        assert_eq!(0, database.row_insert(empty.clone()).await.unwrap());
+
+        let client = ClientBuilder::default().send_empty_requests(true).build();
+        let database = Database::new_with_dbname("public", client);
+        assert!(database.row_insert(empty).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_timezone() {
+        let database = Database::new_with_dbname("public", ClientBuilder::default().build());
+        let header = |database: &Database| {
+            database
+                .to_rpc_request(Request::RowInserts(RowInsertRequests::default()))
+                .header
+                .unwrap()
+        };
+        assert_eq!("", header(&database).timezone);
+
+        for timezone in [
+            "UTC",
+            "Asia/Shanghai",
+            "America/Argentina/Buenos_Aires",
+            "+08:00",
+            "-03:30",
+        ] {
+            let database = database.clone().with_timezone(timezone).unwrap();
+            assert_eq!(timezone, header(&database).timezone);
+        }
+        for timezone in ["", "+8", "+25:00", "+08:60", "Asia Shanghai", "8:00"] {
+            let err = database.clone().with_timezone(timezone).unwrap_err();
+            assert!(matches!(err, Error::InvalidTimezone { .. }));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_row_insert_to_unknown_peer() {
+        let client = ClientBuilder::default()
+            .peers(vec!["127.0.0.1:4001"])
+            .build();
+        let database = Database::new_with_dbname("public", client);
+        let err = database
+            .row_insert_to_peer("127.0.0.1:4002", RowInsertRequests::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::UnknownPeer { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_physical_table_hint() {
+        let database = Database::new_with_dbname("public", ClientBuilder::default().build());
+        assert!(database
+            .request_metadata(None)
+            .unwrap()
+            .get(HINTS_KEY)
+            .is_none());
+
+        let database = database.with_physical_table("greptime_physical_table");
+        let metadata = database.request_metadata(None).unwrap();
+        assert_eq!(
+            "physical_table=greptime_physical_table",
+            metadata.get(HINTS_KEY).unwrap().to_str().unwrap()
+        );
+        let metadata = database.request_metadata(Some("append_mode=true")).unwrap();
+        assert_eq!(
+            "append_mode=true,physical_table=greptime_physical_table",
+            metadata.get(HINTS_KEY).unwrap().to_str().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_trace_context() {
+        let traceparent = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01";
+        let client = ClientBuilder::default()
+            .trace_context_provider(move || {
+                HashMap::from([("traceparent".to_string(), traceparent.to_string())])
+            })
+            .build();
+        let database = Database::new_with_dbname("public", client);
+
+        let metadata = database.request_metadata(None).unwrap();
+        assert_eq!(
+            traceparent,
+            metadata.get("traceparent").unwrap().to_str().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ttl_hint() {
+        let database = Database::new_with_dbname("public", ClientBuilder::default().build());
+        assert!(database.clone().with_ttl(Duration::ZERO).is_err());
+        assert!(database
+            .clone()
+            .with_ttl(Duration::from_millis(1500))
+            .is_err());
+
+        let database = database
+            .with_physical_table("greptime_physical_table")
+            .with_ttl(Duration::from_secs(3600))
+            .unwrap();
+        let metadata = database.request_metadata(None).unwrap();
+        assert_eq!(
+            "physical_table=greptime_physical_table,ttl=3600s",
+            metadata.get(HINTS_KEY).unwrap().to_str().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_check_message_size() {
+        let request = GreptimeRequest {
+            header: None,
+            request: Some(Request::RowInserts(RowInsertRequests {
+                inserts: vec![RowInsertRequest {
+                    table_name: "t".repeat(100),
+                    rows: None,
+                }],
+            })),
+        };
+
+        assert!(check_message_size(&request, None).is_ok());
+        assert!(check_message_size(&request, Some(1024)).is_ok());
+        assert!(matches!(
+            check_message_size(&request, Some(64)),
+            Err(Error::MessageTooLarge { limit: 64, .. })
+        ));
+
+        let request = GreptimeRequest {
+            header: None,
+            request: Some(Request::RowInserts(RowInsertRequests {
+                inserts: vec![RowInsertRequest {
+                    table_name: "wide".to_string(),
+                    rows: Some(Rows {
+                        schema: vec![field("value", ColumnDataType::Float64)],
+                        rows: vec![
+                            Row {
+                                values: vec![f64_value(1.0)],
+                            },
+                            Row {
+                                values: vec![f64_value(2.0); 16],
+                            },
+                        ],
+                    }),
+                }],
+            })),
+        };
+        assert!(matches!(
+            check_message_size(&request, Some(64)),
+            Err(Error::RowTooLarge {
+                row_index: 1,
+                limit: 64,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_parse_dbname() {
+        assert_eq!(("greptime", "public"), parse_dbname("public"));
+        assert_eq!(
+            ("my_catalog", "my_schema"),
+            parse_dbname("my_catalog-my_schema")
+        );
+        assert_eq!(
+            ("tenant", "db-with-dash"),
+            parse_dbname("tenant-db-with-dash")
+        );
+        assert_eq!(("greptime", ""), parse_dbname(""));
+    }
+}
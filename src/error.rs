@@ -12,10 +12,15 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashMap;
 use std::io;
+use std::time::Duration;
 
 use snafu::{Location, Snafu};
-use tonic::Status;
+use tonic::metadata::KeyAndValueRef;
+use tonic::{Code, Status};
+
+use crate::api::v1::ColumnDataType;
 
 #[derive(Debug, Snafu)]
 #[snafu(visibility(pub))]
@@ -45,8 +50,20 @@ pub enum Error {
     MissingField { field: String, location: Location },
 
     // Server error carried in Tonic Status's metadata.
-    #[snafu(display("{}", msg))]
-    Server { status: Status, msg: String },
+    #[snafu(display(
+        "{}{}",
+        msg,
+        request_id
+            .as_ref()
+            .map(|id| format!(", request id: {id}"))
+            .unwrap_or_default()
+    ))]
+    Server {
+        status: Status,
+        msg: String,
+        request_id: Option<String>,
+        metadata: HashMap<String, String>,
+    },
 
     #[snafu(display("Illegal Database response: {err_msg}"))]
     IllegalDatabaseResponse { err_msg: String },
@@ -54,6 +71,216 @@ pub enum Error {
     #[snafu(display("Failed to send request with streaming: {}", err_msg))]
     ClientStreaming { err_msg: String, location: Location },
 
+    #[snafu(display("No schema provided for table: {}", table))]
+    MissingTableSchema { table: String, location: Location },
+
+    #[snafu(display("Conflicting schemas for table: {}", table))]
+    SchemaConflict { table: String, location: Location },
+
+    #[snafu(display("Timed out after {:?}", timeout))]
+    Timeout {
+        timeout: Duration,
+        location: Location,
+    },
+
+    #[snafu(display("Cannot coerce {} to {:?}", value, datatype))]
+    InvalidCoercion {
+        value: String,
+        datatype: ColumnDataType,
+        location: Location,
+    },
+
+    #[snafu(display("Expect a vector of dimension {}, but got {}", expected, actual))]
+    VectorDimensionMismatch {
+        expected: usize,
+        actual: usize,
+        location: Location,
+    },
+
+    #[snafu(display("Failed to read CSV: {}", msg))]
+    ReadCsv { msg: String, location: Location },
+
+    #[snafu(display(
+        "Invalid CSV record at line {}, expect {} fields, but got {}",
+        line,
+        expected,
+        actual
+    ))]
+    CsvColumnCount {
+        line: u64,
+        expected: usize,
+        actual: usize,
+        location: Location,
+    },
+
+    #[snafu(display("Invalid CSV field at line {}, column {}: {}", line, column, msg))]
+    InvalidCsvField {
+        line: u64,
+        column: String,
+        msg: String,
+        location: Location,
+    },
+
+    #[snafu(display("Invalid TTL {:?}, expect a non-zero whole number of seconds", ttl))]
+    InvalidTtl { ttl: Duration, location: Location },
+
+    #[snafu(display("Cannot infer the datatype of column {} from a null value", column))]
+    UntypedValue { column: String, location: Location },
+
+    #[snafu(display(
+        "Request of {} bytes exceeds the max message size of {} bytes, split it into smaller requests",
+        size,
+        limit
+    ))]
+    MessageTooLarge {
+        size: usize,
+        limit: usize,
+        location: Location,
+    },
+
+    #[snafu(display(
+        "Row {} of table {} is {} bytes, over the max message size of {} bytes, split the table vertically into tables of fewer columns",
+        row_index,
+        table,
+        size,
+        limit
+    ))]
+    RowTooLarge {
+        table: String,
+        row_index: usize,
+        size: usize,
+        limit: usize,
+        location: Location,
+    },
+
+    #[snafu(display("Column {} is not in the schema", column))]
+    UnknownColumn { column: String, location: Location },
+
+    #[snafu(display("Missing value for column: {}", column))]
+    MissingColumnValue { column: String, location: Location },
+
+    #[snafu(display(
+        "Row {} has {} values, but the schema has {} columns",
+        row,
+        actual,
+        expected
+    ))]
+    RowLengthMismatch {
+        row: usize,
+        expected: usize,
+        actual: usize,
+        location: Location,
+    },
+
+    #[snafu(display(
+        "Integer width mismatch for column {}, expect {:?}, but got {:?}",
+        column,
+        expected,
+        actual
+    ))]
+    IntegerWidthMismatch {
+        column: String,
+        expected: ColumnDataType,
+        actual: ColumnDataType,
+        location: Location,
+    },
+
+    #[snafu(display(
+        "Value type mismatch for column {}, expect {:?}, but got {:?}",
+        column,
+        expected,
+        actual
+    ))]
+    ValueTypeMismatch {
+        column: String,
+        expected: ColumnDataType,
+        actual: ColumnDataType,
+        location: Location,
+    },
+
+    #[snafu(display("Failed to decode request, source: {}", source))]
+    DecodeRequest {
+        source: prost::DecodeError,
+        location: Location,
+    },
+
+    #[snafu(display("Too many requests in flight, the limit is {}", limit))]
+    TooManyRequests { limit: usize, location: Location },
+
+    #[snafu(display("Peer {} is not configured in the client", peer))]
+    UnknownPeer { peer: String, location: Location },
+
+    #[snafu(display(
+        "Timestamp of row {} in column {} is more than {:?} in the future",
+        row,
+        column,
+        max_skew
+    ))]
+    FutureTimestamp {
+        column: String,
+        row: usize,
+        max_skew: Duration,
+        location: Location,
+    },
+
+    #[snafu(display("Schema mismatch for table {}: {}", table, msg))]
+    SchemaMismatch {
+        table: String,
+        msg: String,
+        location: Location,
+    },
+
+    #[snafu(display("Invalid timezone: {}", timezone))]
+    InvalidTimezone {
+        timezone: String,
+        location: Location,
+    },
+
+    #[snafu(display(
+        "All peers failed: {}, last error: {}",
+        attempted.join(", "),
+        source
+    ))]
+    FailoverExhausted {
+        attempted: Vec<String>,
+        source: Box<Error>,
+        location: Location,
+    },
+
+    #[snafu(display(
+        "Null value in non-nullable column {} of table {} at row {}",
+        column,
+        table,
+        row_index
+    ))]
+    NullInNonNullableColumn {
+        table: String,
+        column: String,
+        row_index: usize,
+        location: Location,
+    },
+
+    #[snafu(display("Invalid UTF-8 string, source: {}", source))]
+    InvalidUtf8 {
+        source: std::str::Utf8Error,
+        location: Location,
+    },
+
+    #[snafu(display("Invalid interval {} at position {}: {}", input, position, msg))]
+    InvalidInterval {
+        input: String,
+        position: usize,
+        msg: String,
+        location: Location,
+    },
+
+    #[snafu(display("Invalid config field {}: {}", field, msg))]
+    InvalidConfig {
+        field: String,
+        msg: String,
+        location: Location,
+    },
+
     #[snafu(display("Failed to parse ascii string: {}", value))]
     InvalidAscii {
         value: String,
@@ -66,6 +293,10 @@ pub type Result<T> = std::result::Result<T, Error>;
 
 pub const INNER_ERROR_MSG: &str = "INNER_ERROR_MSG";
 
+/// Metadata set by the transport rather than the server, left out of
+/// [`Error::server_metadata`].
+const TRANSPORT_METADATA_KEYS: &[&str] = &["content-type", "date", "grpc-status", "grpc-message"];
+
 impl From<Status> for Error {
     fn from(e: Status) -> Self {
         fn get_metadata_value(e: &Status, key: &str) -> Option<String> {
@@ -75,19 +306,384 @@ impl From<Status> for Error {
         }
 
         let msg = get_metadata_value(&e, INNER_ERROR_MSG).unwrap_or(e.to_string());
+        let metadata = e
+            .metadata()
+            .iter()
+            .filter_map(|entry| match entry {
+                KeyAndValueRef::Ascii(key, value) => {
+                    Some((key.as_str().to_string(), value.to_str().ok()?.to_string()))
+                }
+                KeyAndValueRef::Binary(..) => None,
+            })
+            .filter(|(key, _)| !TRANSPORT_METADATA_KEYS.contains(&key.as_str()))
+            .collect();
+
+        Self::Server {
+            status: e,
+            msg,
+            request_id: None,
+            metadata,
+        }
+    }
+}
 
-        Self::Server { status: e, msg }
+impl From<Error> for Status {
+    /// Map the error to the gRPC status code describing it best. Server errors
+    /// give back the status the server sent, with its code and metadata.
+    fn from(e: Error) -> Self {
+        let code = match e {
+            Error::Server { status, .. } => return status,
+            Error::CreateChannel { .. }
+            | Error::IllegalGrpcClientState { .. }
+            | Error::ClientStreaming { .. }
+            | Error::FailoverExhausted { .. } => Code::Unavailable,
+            Error::Timeout { .. } => Code::DeadlineExceeded,
+            Error::MessageTooLarge { .. }
+            | Error::RowTooLarge { .. }
+            | Error::TooManyRequests { .. } => Code::ResourceExhausted,
+            Error::InvalidTlsConfig { .. }
+            | Error::InvalidConfigFilePath { .. }
+            | Error::InvalidConfig { .. } => Code::FailedPrecondition,
+            Error::IllegalDatabaseResponse { .. } => Code::Internal,
+            Error::UnknownColumnDataType { .. }
+            | Error::MissingField { .. }
+            | Error::DecodeRequest { .. }
+            | Error::MissingTableSchema { .. }
+            | Error::SchemaConflict { .. }
+            | Error::InvalidCoercion { .. }
+            | Error::VectorDimensionMismatch { .. }
+            | Error::ReadCsv { .. }
+            | Error::CsvColumnCount { .. }
+            | Error::InvalidCsvField { .. }
+            | Error::InvalidTtl { .. }
+            | Error::UntypedValue { .. }
+            | Error::UnknownColumn { .. }
+            | Error::MissingColumnValue { .. }
+            | Error::RowLengthMismatch { .. }
+            | Error::IntegerWidthMismatch { .. }
+            | Error::ValueTypeMismatch { .. }
+            | Error::UnknownPeer { .. }
+            | Error::FutureTimestamp { .. }
+            | Error::SchemaMismatch { .. }
+            | Error::InvalidTimezone { .. }
+            | Error::NullInNonNullableColumn { .. }
+            | Error::InvalidUtf8 { .. }
+            | Error::InvalidInterval { .. }
+            | Error::InvalidAscii { .. } => Code::InvalidArgument,
+        };
+        Status::new(code, e.to_string())
     }
 }
 
 impl Error {
-    /// Indicate if the error is retriable
+    /// An [`Error::Server`] as if the server responded with `code` and `msg`,
+    /// e.g. to test retry logic built on [`Error::is_retriable`] or
+    /// [`Error::is_peer_failure`] without a live server.
+    pub fn from_code(code: Code, msg: impl Into<String>) -> Self {
+        Self::from(Status::new(code, msg))
+    }
+
+    /// The client-generated id of the request that caused this error, if the
+    /// error came from the server. Use it to correlate with server-side logs.
+    pub fn request_id(&self) -> Option<&str> {
+        match self {
+            Self::Server { request_id, .. } => request_id.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// All the ASCII metadata the server sent along with its error, such as
+    /// [`INNER_ERROR_MSG`], error codes or trace ids, keyed by lowercase name.
+    /// `None` if the error didn't come from the server.
+    pub fn server_metadata(&self) -> Option<&HashMap<String, String>> {
+        match self {
+            Self::Server { metadata, .. } => Some(metadata),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn with_request_id(mut self, id: &str) -> Self {
+        if let Self::Server { request_id, .. } = &mut self {
+            *request_id = Some(id.to_string());
+        }
+        self
+    }
+
+    /// Whether the error tells the peer couldn't serve the request, e.g. it is
+    /// down or overloaded, so another peer may. Errors about the request
+    /// itself would fail anywhere.
+    pub fn is_peer_failure(&self) -> bool {
+        match self {
+            Self::CreateChannel { .. } | Self::Timeout { .. } => true,
+            Self::Server { status, .. } => {
+                matches!(status.code(), Code::Unavailable | Code::DeadlineExceeded)
+            }
+            _ => false,
+        }
+    }
+
+    /// The name of the variant, e.g. `"Server"`, to count errors by kind.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::InvalidTlsConfig { .. } => "InvalidTlsConfig",
+            Self::InvalidConfigFilePath { .. } => "InvalidConfigFilePath",
+            Self::CreateChannel { .. } => "CreateChannel",
+            Self::UnknownColumnDataType { .. } => "UnknownColumnDataType",
+            Self::IllegalGrpcClientState { .. } => "IllegalGrpcClientState",
+            Self::MissingField { .. } => "MissingField",
+            Self::Server { .. } => "Server",
+            Self::IllegalDatabaseResponse { .. } => "IllegalDatabaseResponse",
+            Self::ClientStreaming { .. } => "ClientStreaming",
+            Self::MissingTableSchema { .. } => "MissingTableSchema",
+            Self::SchemaConflict { .. } => "SchemaConflict",
+            Self::Timeout { .. } => "Timeout",
+            Self::InvalidCoercion { .. } => "InvalidCoercion",
+            Self::VectorDimensionMismatch { .. } => "VectorDimensionMismatch",
+            Self::ReadCsv { .. } => "ReadCsv",
+            Self::CsvColumnCount { .. } => "CsvColumnCount",
+            Self::InvalidCsvField { .. } => "InvalidCsvField",
+            Self::InvalidTtl { .. } => "InvalidTtl",
+            Self::UntypedValue { .. } => "UntypedValue",
+            Self::MessageTooLarge { .. } => "MessageTooLarge",
+            Self::RowTooLarge { .. } => "RowTooLarge",
+            Self::UnknownColumn { .. } => "UnknownColumn",
+            Self::MissingColumnValue { .. } => "MissingColumnValue",
+            Self::RowLengthMismatch { .. } => "RowLengthMismatch",
+            Self::IntegerWidthMismatch { .. } => "IntegerWidthMismatch",
+            Self::ValueTypeMismatch { .. } => "ValueTypeMismatch",
+            Self::DecodeRequest { .. } => "DecodeRequest",
+            Self::TooManyRequests { .. } => "TooManyRequests",
+            Self::UnknownPeer { .. } => "UnknownPeer",
+            Self::FutureTimestamp { .. } => "FutureTimestamp",
+            Self::SchemaMismatch { .. } => "SchemaMismatch",
+            Self::InvalidTimezone { .. } => "InvalidTimezone",
+            Self::FailoverExhausted { .. } => "FailoverExhausted",
+            Self::NullInNonNullableColumn { .. } => "NullInNonNullableColumn",
+            Self::InvalidUtf8 { .. } => "InvalidUtf8",
+            Self::InvalidInterval { .. } => "InvalidInterval",
+            Self::InvalidConfig { .. } => "InvalidConfig",
+            Self::InvalidAscii { .. } => "InvalidAscii",
+        }
+    }
+
+    /// Indicate if the error is retriable, i.e. sending the same request
+    /// again may succeed: transport failures, timeouts, load shedding, and
+    /// server errors with an `Unavailable`, `DeadlineExceeded`,
+    /// `ResourceExhausted` or `Aborted` code.
+    ///
+    /// Errors about the request or the configuration, e.g. validation
+    /// failures or a message over the size limit, fail the same way every
+    /// time and are not retriable.
     pub fn is_retriable(&self) -> bool {
-        !matches!(
-            self,
-            Self::InvalidTlsConfig { .. }
-                | Self::MissingField { .. }
-                | Self::InvalidConfigFilePath { .. }
-        )
+        match self {
+            Self::CreateChannel { .. }
+            | Self::IllegalGrpcClientState { .. }
+            | Self::ClientStreaming { .. }
+            | Self::Timeout { .. }
+            | Self::TooManyRequests { .. }
+            | Self::FailoverExhausted { .. } => true,
+            Self::Server { status, .. } => matches!(
+                status.code(),
+                Code::Unavailable
+                    | Code::DeadlineExceeded
+                    | Code::ResourceExhausted
+                    | Code::Aborted
+            ),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use prost::Message;
+    use snafu::ResultExt;
+    use tonic::{Code, Status};
+
+    use super::*;
+    use crate::api::v1::RowInsertRequests;
+
+    #[test]
+    fn test_server_metadata() {
+        let mut status = Status::internal("failed");
+        let metadata = status.metadata_mut();
+        // Keys are case-insensitive, the server's `INNER_ERROR_MSG` included.
+        metadata.insert("inner_error_msg", "Table not found".parse().unwrap());
+        metadata.insert("x-greptime-err-code", "4001".parse().unwrap());
+        metadata.insert("content-type", "application/grpc".parse().unwrap());
+
+        let err = Error::from(status);
+        assert_eq!("Table not found", err.to_string());
+        let metadata = err.server_metadata().unwrap();
+        assert_eq!(2, metadata.len());
+        assert_eq!("Table not found", metadata["inner_error_msg"]);
+        assert_eq!("4001", metadata["x-greptime-err-code"]);
+
+        assert!(UnknownColumnSnafu { column: "c" }
+            .build()
+            .server_metadata()
+            .is_none());
+    }
+
+    #[test]
+    fn test_into_status() {
+        let status = Status::from(Error::from(Status::not_found("no such table")));
+        assert_eq!(Code::NotFound, status.code());
+        assert_eq!("no such table", status.message());
+
+        let err = TimeoutSnafu {
+            timeout: Duration::from_secs(1),
+        }
+        .build();
+        assert_eq!(Code::DeadlineExceeded, Status::from(err).code());
+
+        assert!(Error::from(Status::unavailable("down")).is_peer_failure());
+        assert!(!Error::from(Status::invalid_argument("bad")).is_peer_failure());
+
+        let err = UnknownColumnSnafu { column: "c" }.build();
+        assert!(!err.is_peer_failure());
+        let message = err.to_string();
+        let status = Status::from(err);
+        assert_eq!(Code::InvalidArgument, status.code());
+        assert_eq!(message, status.message());
+    }
+
+    #[test]
+    fn test_from_code() {
+        let err = Error::from_code(Code::Unavailable, "down");
+        assert!(err.is_peer_failure());
+        assert!(err.is_retriable());
+        let status = Status::from(err);
+        assert_eq!(Code::Unavailable, status.code());
+        assert_eq!("down", status.message());
+        assert!(!Error::from_code(Code::InvalidArgument, "bad").is_peer_failure());
+    }
+
+    #[test]
+    fn test_is_retriable() {
+        assert!(TimeoutSnafu {
+            timeout: Duration::from_secs(1)
+        }
+        .build()
+        .is_retriable());
+        assert!(TooManyRequestsSnafu { limit: 1usize }
+            .build()
+            .is_retriable());
+        assert!(Error::from_code(Code::Unavailable, "down").is_retriable());
+        assert!(Error::from_code(Code::ResourceExhausted, "busy").is_retriable());
+        assert!(!Error::from_code(Code::InvalidArgument, "bad").is_retriable());
+        assert!(!Error::from_code(Code::NotFound, "no table").is_retriable());
+
+        let int64 = ColumnDataType::Int64;
+        let validation_errors = vec![
+            InvalidTlsConfigSnafu { msg: "m" }.build(),
+            UnknownColumnDataTypeSnafu { datatype: -1 }.build(),
+            MissingFieldSnafu { field: "f" }.build(),
+            IllegalDatabaseResponseSnafu { err_msg: "m" }.build(),
+            MissingTableSchemaSnafu { table: "t" }.build(),
+            SchemaConflictSnafu { table: "t" }.build(),
+            InvalidCoercionSnafu {
+                value: "v",
+                datatype: int64,
+            }
+            .build(),
+            VectorDimensionMismatchSnafu {
+                expected: 2usize,
+                actual: 3usize,
+            }
+            .build(),
+            ReadCsvSnafu { msg: "m" }.build(),
+            CsvColumnCountSnafu {
+                line: 1u64,
+                expected: 2usize,
+                actual: 3usize,
+            }
+            .build(),
+            InvalidCsvFieldSnafu {
+                line: 1u64,
+                column: "c",
+                msg: "m",
+            }
+            .build(),
+            InvalidTtlSnafu {
+                ttl: Duration::from_millis(1),
+            }
+            .build(),
+            UntypedValueSnafu { column: "c" }.build(),
+            MessageTooLargeSnafu {
+                size: 2usize,
+                limit: 1usize,
+            }
+            .build(),
+            RowTooLargeSnafu {
+                table: "t",
+                row_index: 0usize,
+                size: 2usize,
+                limit: 1usize,
+            }
+            .build(),
+            UnknownColumnSnafu { column: "c" }.build(),
+            MissingColumnValueSnafu { column: "c" }.build(),
+            RowLengthMismatchSnafu {
+                row: 0usize,
+                expected: 2usize,
+                actual: 3usize,
+            }
+            .build(),
+            IntegerWidthMismatchSnafu {
+                column: "c",
+                expected: int64,
+                actual: ColumnDataType::Int32,
+            }
+            .build(),
+            ValueTypeMismatchSnafu {
+                column: "c",
+                expected: int64,
+                actual: ColumnDataType::String,
+            }
+            .build(),
+            RowInsertRequests::decode(&[0xffu8][..])
+                .context(DecodeRequestSnafu)
+                .unwrap_err(),
+            UnknownPeerSnafu { peer: "p" }.build(),
+            FutureTimestampSnafu {
+                column: "ts",
+                row: 0usize,
+                max_skew: Duration::from_secs(1),
+            }
+            .build(),
+            SchemaMismatchSnafu {
+                table: "t",
+                msg: "m",
+            }
+            .build(),
+            InvalidTimezoneSnafu { timezone: "x" }.build(),
+            NullInNonNullableColumnSnafu {
+                table: "t",
+                column: "c",
+                row_index: 0usize,
+            }
+            .build(),
+            std::str::from_utf8(&[0xffu8])
+                .context(InvalidUtf8Snafu)
+                .unwrap_err(),
+            InvalidIntervalSnafu {
+                input: "P",
+                position: 1usize,
+                msg: "m",
+            }
+            .build(),
+            InvalidConfigSnafu {
+                field: "f",
+                msg: "m",
+            }
+            .build(),
+            InvalidAsciiSnafu { value: "v" }.build(),
+        ];
+        for err in validation_errors {
+            assert!(!err.is_retriable(), "{err:?} is retriable");
+        }
     }
 }
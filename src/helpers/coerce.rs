@@ -0,0 +1,234 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use snafu::OptionExt;
+
+use crate::api::v1::value::ValueData;
+use crate::api::v1::{ColumnDataType, Value};
+use crate::error::{InvalidCoercionSnafu, Result};
+use crate::helpers::values::*;
+
+/// Coerce `value` to the `datatype` of its column.
+///
+/// Only lossless conversions are made:
+///
+/// - a null value, or a value already of `datatype`, is kept as is
+/// - integers widen to wider integers of the same signedness, and unsigned
+///   integers also to wider signed integers
+/// - integers widen to floats that represent them exactly: 8 and 16-bit
+///   integers to `Float32`, integers up to 32-bit to `Float64`
+/// - `Float32` widens to `Float64`
+/// - timestamps convert to a finer unit, when the result doesn't overflow
+///
+/// Every other conversion, e.g. narrowing, signed to unsigned or float to
+/// integer, fails with [`Error::InvalidCoercion`](crate::Error::InvalidCoercion).
+pub fn coerce_value(value: Value, datatype: ColumnDataType) -> Result<Value> {
+    let Some(value_data) = &value.value_data else {
+        return Ok(value);
+    };
+    if datatype_of(value_data) == Some(datatype) {
+        return Ok(value);
+    }
+
+    coerce_number(value_data, datatype)
+        .or_else(|| coerce_timestamp(value_data, datatype))
+        .with_context(|| InvalidCoercionSnafu {
+            value: debug_value(&value),
+            datatype,
+        })
+}
+
+enum Number {
+    Signed(i64, u32),
+    Unsigned(u64, u32),
+    Float(f64),
+}
+
+/// Decode a numeric value with the bit width of its type, `None` if it isn't
+/// numeric or doesn't fit its declared width.
+fn number_of(value_data: &ValueData) -> Option<Number> {
+    let number = match *value_data {
+        ValueData::I8Value(v) => Number::Signed(i8::try_from(v).ok()?.into(), 8),
+        ValueData::I16Value(v) => Number::Signed(i16::try_from(v).ok()?.into(), 16),
+        ValueData::I32Value(v) => Number::Signed(v.into(), 32),
+        ValueData::I64Value(v) => Number::Signed(v, 64),
+        ValueData::U8Value(v) => Number::Unsigned(u8::try_from(v).ok()?.into(), 8),
+        ValueData::U16Value(v) => Number::Unsigned(u16::try_from(v).ok()?.into(), 16),
+        ValueData::U32Value(v) => Number::Unsigned(v.into(), 32),
+        ValueData::U64Value(v) => Number::Unsigned(v, 64),
+        ValueData::F32Value(v) => Number::Float(v.into()),
+        _ => return None,
+    };
+    Some(number)
+}
+
+fn coerce_number(value_data: &ValueData, datatype: ColumnDataType) -> Option<Value> {
+    let value = match number_of(value_data)? {
+        Number::Signed(v, bits) => match datatype {
+            ColumnDataType::Int16 if bits <= 16 => i16_value(v.try_into().ok()?),
+            ColumnDataType::Int32 if bits <= 32 => i32_value(v.try_into().ok()?),
+            ColumnDataType::Int64 => i64_value(v),
+            ColumnDataType::Float32 if bits <= 16 => f32_value(v as f32),
+            ColumnDataType::Float64 if bits <= 32 => f64_value(v as f64),
+            _ => return None,
+        },
+        Number::Unsigned(v, bits) => match datatype {
+            ColumnDataType::Uint16 if bits <= 16 => u16_value(v.try_into().ok()?),
+            ColumnDataType::Uint32 if bits <= 32 => u32_value(v.try_into().ok()?),
+            ColumnDataType::Uint64 => u64_value(v),
+            ColumnDataType::Int16 if bits < 16 => i16_value(v.try_into().ok()?),
+            ColumnDataType::Int32 if bits < 32 => i32_value(v.try_into().ok()?),
+            ColumnDataType::Int64 if bits < 64 => i64_value(v.try_into().ok()?),
+            ColumnDataType::Float32 if bits <= 16 => f32_value(v as f32),
+            ColumnDataType::Float64 if bits <= 32 => f64_value(v as f64),
+            _ => return None,
+        },
+        Number::Float(v) => match datatype {
+            ColumnDataType::Float64 => f64_value(v),
+            _ => return None,
+        },
+    };
+    Some(value)
+}
+
+fn coerce_timestamp(value_data: &ValueData, datatype: ColumnDataType) -> Option<Value> {
+    // Units as powers of ten of a second's fraction.
+    let (v, from) = match *value_data {
+        ValueData::TimestampSecondValue(v) => (v, 0),
+        ValueData::TimestampMillisecondValue(v) => (v, 3),
+        ValueData::TimestampMicrosecondValue(v) => (v, 6),
+        ValueData::TimestampNanosecondValue(v) => (v, 9),
+        _ => return None,
+    };
+    let (to, to_value): (u32, fn(i64) -> Value) = match datatype {
+        ColumnDataType::TimestampSecond => (0, timestamp_second_value),
+        ColumnDataType::TimestampMillisecond => (3, timestamp_millisecond_value),
+        ColumnDataType::TimestampMicrosecond => (6, timestamp_microsecond_value),
+        ColumnDataType::TimestampNanosecond => (9, timestamp_nanosecond_value),
+        _ => return None,
+    };
+    let factor = 10_i64.checked_pow(to.checked_sub(from)?)?;
+    Some(to_value(v.checked_mul(factor)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    fn assert_invalid(value: Value, datatype: ColumnDataType) {
+        assert!(matches!(
+            coerce_value(value, datatype),
+            Err(Error::InvalidCoercion { .. })
+        ));
+    }
+
+    #[test]
+    fn test_coerce_identity() {
+        assert_eq!(
+            none_value(),
+            coerce_value(none_value(), ColumnDataType::Int64).unwrap()
+        );
+        assert_eq!(
+            string_value("a".to_string()),
+            coerce_value(string_value("a".to_string()), ColumnDataType::String).unwrap()
+        );
+        assert_invalid(string_value("a".to_string()), ColumnDataType::Binary);
+    }
+
+    #[test]
+    fn test_coerce_integers() {
+        assert_eq!(
+            i64_value(-5),
+            coerce_value(i32_value(-5), ColumnDataType::Int64).unwrap()
+        );
+        assert_eq!(
+            i16_value(-5),
+            coerce_value(i8_value(-5), ColumnDataType::Int16).unwrap()
+        );
+        assert_eq!(
+            i32_value(65535),
+            coerce_value(u16_value(65535), ColumnDataType::Int32).unwrap()
+        );
+        assert_eq!(
+            u64_value(7),
+            coerce_value(u8_value(7), ColumnDataType::Uint64).unwrap()
+        );
+
+        // Narrowing and sign changes are lossy.
+        assert_invalid(i64_value(1), ColumnDataType::Int32);
+        assert_invalid(i32_value(1), ColumnDataType::Uint64);
+        assert_invalid(u32_value(1), ColumnDataType::Int32);
+        assert_invalid(u64_value(1), ColumnDataType::Int64);
+        // A payload out of its declared width is invalid.
+        assert_invalid(
+            Value {
+                value_data: Some(ValueData::I8Value(300)),
+            },
+            ColumnDataType::Int16,
+        );
+    }
+
+    #[test]
+    fn test_coerce_floats() {
+        assert_eq!(
+            f64_value(1.5),
+            coerce_value(f32_value(1.5), ColumnDataType::Float64).unwrap()
+        );
+        assert_eq!(
+            f32_value(-300.0),
+            coerce_value(i16_value(-300), ColumnDataType::Float32).unwrap()
+        );
+        assert_eq!(
+            f64_value(4294967295.0),
+            coerce_value(u32_value(u32::MAX), ColumnDataType::Float64).unwrap()
+        );
+
+        assert_invalid(i32_value(1), ColumnDataType::Float32);
+        assert_invalid(i64_value(1), ColumnDataType::Float64);
+        assert_invalid(f64_value(1.0), ColumnDataType::Float32);
+        assert_invalid(f32_value(1.0), ColumnDataType::Int64);
+    }
+
+    #[test]
+    fn test_coerce_timestamps() {
+        assert_eq!(
+            timestamp_millisecond_value(1686109527000),
+            coerce_value(
+                timestamp_second_value(1686109527),
+                ColumnDataType::TimestampMillisecond
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            timestamp_nanosecond_value(1_000),
+            coerce_value(
+                timestamp_microsecond_value(1),
+                ColumnDataType::TimestampNanosecond
+            )
+            .unwrap()
+        );
+
+        // Coarser units lose precision, and overflows are rejected.
+        assert_invalid(
+            timestamp_millisecond_value(1),
+            ColumnDataType::TimestampSecond,
+        );
+        assert_invalid(
+            timestamp_second_value(i64::MAX / 10),
+            ColumnDataType::TimestampNanosecond,
+        );
+        assert_invalid(i64_value(1), ColumnDataType::TimestampMillisecond);
+    }
+}
@@ -0,0 +1,200 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::Display;
+use std::io::Read;
+use std::str::FromStr;
+
+use snafu::{ensure, OptionExt};
+
+use crate::api::v1::*;
+use crate::error::{
+    CsvColumnCountSnafu, InvalidCsvFieldSnafu, ReadCsvSnafu, Result, UnknownColumnDataTypeSnafu,
+};
+use crate::helpers::values::*;
+
+/// Parse CSV from `reader` into a `RowInsertRequests` for `table`.
+///
+/// The first line is a header and is skipped, the fields of every other record
+/// are matched with `schema` by position and parsed according to their
+/// column's datatype. Quoted fields are unquoted, and empty fields are null.
+/// Temporal columns take the integer value in their unit, e.g. milliseconds
+/// for `TimestampMillisecond`, decimal columns their unscaled integer value,
+/// and binary columns the field's bytes.
+///
+/// Errors report the line, and the column when a field can't be parsed.
+pub fn from_csv_reader<R: Read>(
+    reader: R,
+    table: &str,
+    schema: Vec<ColumnSchema>,
+) -> Result<RowInsertRequests> {
+    let mut datatypes = Vec::with_capacity(schema.len());
+    for column in &schema {
+        let datatype =
+            ColumnDataType::try_from(column.datatype)
+                .ok()
+                .context(UnknownColumnDataTypeSnafu {
+                    datatype: column.datatype,
+                })?;
+        datatypes.push(datatype);
+    }
+
+    let mut rows = Vec::new();
+    for record in ::csv::Reader::from_reader(reader).records() {
+        let record = record.map_err(|e| ReadCsvSnafu { msg: e.to_string() }.build())?;
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+        ensure!(
+            record.len() == schema.len(),
+            CsvColumnCountSnafu {
+                line,
+                expected: schema.len(),
+                actual: record.len(),
+            }
+        );
+
+        let mut values = Vec::with_capacity(schema.len());
+        for ((field, column), datatype) in record.iter().zip(&schema).zip(&datatypes) {
+            let value = parse_field(field, *datatype).map_err(|msg| {
+                InvalidCsvFieldSnafu {
+                    line,
+                    column: &column.column_name,
+                    msg,
+                }
+                .build()
+            })?;
+            values.push(value);
+        }
+        rows.push(Row { values });
+    }
+
+    Ok(RowInsertRequests {
+        inserts: vec![RowInsertRequest {
+            table_name: table.to_string(),
+            rows: Some(Rows { schema, rows }),
+        }],
+    })
+}
+
+fn parse_field(field: &str, datatype: ColumnDataType) -> std::result::Result<Value, String> {
+    fn parse<T>(field: &str) -> std::result::Result<T, String>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        field
+            .trim()
+            .parse()
+            .map_err(|e: T::Err| format!("{e}: {field:?}"))
+    }
+
+    if field.is_empty() {
+        return Ok(none_value());
+    }
+    let value = match datatype {
+        ColumnDataType::Boolean => bool_value(parse(field)?),
+        ColumnDataType::Int8 => i8_value(parse(field)?),
+        ColumnDataType::Int16 => i16_value(parse(field)?),
+        ColumnDataType::Int32 => i32_value(parse(field)?),
+        ColumnDataType::Int64 => i64_value(parse(field)?),
+        ColumnDataType::Uint8 => u8_value(parse(field)?),
+        ColumnDataType::Uint16 => u16_value(parse(field)?),
+        ColumnDataType::Uint32 => u32_value(parse(field)?),
+        ColumnDataType::Uint64 => u64_value(parse(field)?),
+        ColumnDataType::Float32 => f32_value(parse(field)?),
+        ColumnDataType::Float64 => f64_value(parse(field)?),
+        ColumnDataType::String => string_value(field.to_string()),
+        ColumnDataType::Binary => binary_value(field.as_bytes().to_vec()),
+        ColumnDataType::Date => date_value(parse(field)?),
+        ColumnDataType::Datetime => datetime_value(parse(field)?),
+        ColumnDataType::TimestampSecond => timestamp_second_value(parse(field)?),
+        ColumnDataType::TimestampMillisecond => timestamp_millisecond_value(parse(field)?),
+        ColumnDataType::TimestampMicrosecond => timestamp_microsecond_value(parse(field)?),
+        ColumnDataType::TimestampNanosecond => timestamp_nanosecond_value(parse(field)?),
+        ColumnDataType::TimeSecond => time_second_value(parse(field)?),
+        ColumnDataType::TimeMillisecond => time_millisecond_value(parse(field)?),
+        ColumnDataType::TimeMicrosecond => time_microsecond_value(parse(field)?),
+        ColumnDataType::TimeNanosecond => time_nanosecond_value(parse(field)?),
+        ColumnDataType::Decimal128 => decimal128_value(parse(field)?),
+        datatype => return Err(format!("unsupported datatype {datatype:?}")),
+    };
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::schema::*;
+    use crate::Error;
+
+    fn weather_schema() -> Vec<ColumnSchema> {
+        vec![
+            tag("city", ColumnDataType::String),
+            timestamp("ts", ColumnDataType::TimestampMillisecond),
+            field("temperature", ColumnDataType::Float64),
+        ]
+    }
+
+    #[test]
+    fn test_from_csv_reader() {
+        let csv = "city,ts,temperature\n\"San Francisco, CA\",1686109527000,21.5\nBeijing,1686109528000,\n";
+        let requests = from_csv_reader(csv.as_bytes(), "weather", weather_schema()).unwrap();
+
+        assert_eq!(1, requests.inserts.len());
+        let request = &requests.inserts[0];
+        assert_eq!("weather", request.table_name);
+        let rows = request.rows.as_ref().unwrap();
+        assert_eq!(weather_schema(), rows.schema);
+        assert_eq!(
+            vec![
+                Row {
+                    values: vec![
+                        string_value("San Francisco, CA".to_string()),
+                        timestamp_millisecond_value(1686109527000),
+                        f64_value(21.5),
+                    ],
+                },
+                Row {
+                    values: vec![
+                        string_value("Beijing".to_string()),
+                        timestamp_millisecond_value(1686109528000),
+                        none_value(),
+                    ],
+                },
+            ],
+            rows.rows
+        );
+    }
+
+    #[test]
+    fn test_from_csv_reader_errors() {
+        let csv = "city,ts,temperature\nBeijing,1686109528000,21.5\nBeijing,yesterday,21.5\n";
+        let err = from_csv_reader(csv.as_bytes(), "weather", weather_schema()).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::InvalidCsvField { line: 3, ref column, .. } if column == "ts"
+        ));
+
+        let csv = "city,ts\nBeijing,1686109528000\n";
+        let err = from_csv_reader(csv.as_bytes(), "weather", weather_schema()).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::CsvColumnCount {
+                line: 2,
+                expected: 3,
+                actual: 2,
+                ..
+            }
+        ));
+    }
+}
@@ -0,0 +1,150 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use snafu::OptionExt;
+
+use crate::api::v1::*;
+use crate::error::{Result, UntypedValueSnafu};
+use crate::helpers::schema::{tag, timestamp};
+use crate::helpers::values::datatype_of;
+
+/// Build a multi-table `RowDeleteRequests` from `(table, rows)` pairs.
+///
+/// Each `rows` carries the key columns (tags and timestamp) of the rows to
+/// delete from that table; a row is deleted when all its key values match.
+pub fn row_delete_requests<I, T>(deletes: I) -> RowDeleteRequests
+where
+    I: IntoIterator<Item = (T, Rows)>,
+    T: Into<String>,
+{
+    RowDeleteRequests {
+        deletes: deletes
+            .into_iter()
+            .map(|(table_name, rows)| RowDeleteRequest {
+                table_name: table_name.into(),
+                rows: Some(rows),
+            })
+            .collect(),
+    }
+}
+
+/// Start building a `RowDeleteRequest` removing a single row of `table`:
+///
+/// ```ignore
+/// let request = delete_request("monitor")
+///     .key("host", string_value("127.0.0.1".to_string()))
+///     .timestamp("ts", timestamp_millisecond_value(1686109527000))
+///     .build()?;
+/// ```
+///
+/// Deletes match rows by their whole key, so give every tag of the table and
+/// its time index. Deleting a time range isn't supported by the protocol.
+pub fn delete_request(table: &str) -> RowDeleteBuilder {
+    RowDeleteBuilder {
+        table_name: table.to_string(),
+        columns: Vec::new(),
+    }
+}
+
+/// Builder of a single-row `RowDeleteRequest`, see [`delete_request`].
+#[derive(Debug, Clone)]
+pub struct RowDeleteBuilder {
+    table_name: String,
+    columns: Vec<(String, SemanticType, Value)>,
+}
+
+impl RowDeleteBuilder {
+    /// Match the tag column `name` with `value`.
+    pub fn key(mut self, name: &str, value: Value) -> Self {
+        self.columns
+            .push((name.to_string(), SemanticType::Tag, value));
+        self
+    }
+
+    /// Match the time index column `name` with `value`.
+    pub fn timestamp(mut self, name: &str, value: Value) -> Self {
+        self.columns
+            .push((name.to_string(), SemanticType::Timestamp, value));
+        self
+    }
+
+    /// Build the request. The datatype of each column is taken from its value,
+    /// so fails with [`Error::UntypedValue`](crate::Error::UntypedValue) on a
+    /// null value.
+    pub fn build(self) -> Result<RowDeleteRequest> {
+        let mut schema = Vec::with_capacity(self.columns.len());
+        let mut values = Vec::with_capacity(self.columns.len());
+        for (name, semantic_type, value) in self.columns {
+            let datatype = value
+                .value_data
+                .as_ref()
+                .and_then(datatype_of)
+                .context(UntypedValueSnafu { column: &name })?;
+            schema.push(match semantic_type {
+                SemanticType::Timestamp => timestamp(&name, datatype),
+                _ => tag(&name, datatype),
+            });
+            values.push(value);
+        }
+
+        Ok(RowDeleteRequest {
+            table_name: self.table_name,
+            rows: Some(Rows {
+                schema,
+                rows: vec![Row { values }],
+            }),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::values::{none_value, string_value, timestamp_millisecond_value};
+    use crate::Error;
+
+    #[test]
+    fn test_delete_request() {
+        let request = delete_request("monitor")
+            .key("host", string_value("127.0.0.1".to_string()))
+            .timestamp("ts", timestamp_millisecond_value(1686109527000))
+            .build()
+            .unwrap();
+
+        assert_eq!("monitor", request.table_name);
+        let rows = request.rows.unwrap();
+        assert_eq!(
+            vec![
+                tag("host", ColumnDataType::String),
+                timestamp("ts", ColumnDataType::TimestampMillisecond),
+            ],
+            rows.schema
+        );
+        assert_eq!(
+            vec![Row {
+                values: vec![
+                    string_value("127.0.0.1".to_string()),
+                    timestamp_millisecond_value(1686109527000),
+                ],
+            }],
+            rows.rows
+        );
+
+        let err = delete_request("monitor")
+            .key("host", none_value())
+            .build()
+            .unwrap_err();
+        assert!(matches!(err, Error::UntypedValue { ref column, .. } if column == "host"));
+    }
+}
@@ -12,5 +12,12 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod coerce;
+#[cfg(feature = "csv")]
+pub mod csv;
+pub mod delete;
+pub mod prometheus;
+pub mod requests;
+pub mod rows;
 pub mod schema;
 pub mod values;
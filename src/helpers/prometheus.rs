@@ -0,0 +1,352 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Helpers to write Prometheus-shaped time series, following GreptimeDB's
+//! metric conventions: a table per metric, a tag per label, the sample time in
+//! a millisecond `greptime_timestamp` column and its value in a `greptime_value`
+//! field.
+//!
+//! Histograms and summaries are expanded the way Prometheus exposes them: a
+//! `<metric>_bucket` table with an `le` tag per bucket bound, or a `<metric>`
+//! table with a `quantile` tag, plus `<metric>_sum` and `<metric>_count`
+//! tables of plain samples.
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::api::v1::*;
+use crate::helpers::schema::{field, tag, timestamp};
+use crate::helpers::values::{f64_value, none_value, string_value, timestamp_millisecond_value};
+
+/// The time index column of metric tables.
+pub const TIMESTAMP_COLUMN: &str = "greptime_timestamp";
+/// The value column of metric tables.
+pub const VALUE_COLUMN: &str = "greptime_value";
+/// The bucket upper bound tag of histogram tables.
+pub const BUCKET_LABEL: &str = "le";
+/// The quantile tag of summary tables.
+pub const QUANTILE_LABEL: &str = "quantile";
+
+/// A histogram sample: cumulative `(upper bound, count)` buckets, with the
+/// sum and count of all observations.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Histogram {
+    pub buckets: Vec<(f64, u64)>,
+    pub sum: f64,
+    pub count: u64,
+}
+
+/// A summary sample: `(quantile, value)` pairs, with the sum and count of all
+/// observations.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Summary {
+    pub quantiles: Vec<(f64, f64)>,
+    pub sum: f64,
+    pub count: u64,
+}
+
+/// The labels and `(millisecond timestamp, value)` samples of a series.
+type Series = (HashMap<String, String>, Vec<(i64, f64)>);
+
+/// Build requests from `(metric, labels, samples)` time series, samples being
+/// `(millisecond timestamp, value)` pairs.
+///
+/// Series of the same metric are grouped in one request on the metric's
+/// table, in the order metrics first appear. Series may have different label
+/// sets: the table has a tag for every label of its series, sorted by name,
+/// and a series misses the labels it doesn't have as nulls.
+pub fn prometheus_requests<I, M>(series: I) -> RowInsertRequests
+where
+    I: IntoIterator<Item = (M, HashMap<String, String>, Vec<(i64, f64)>)>,
+    M: Into<String>,
+{
+    let mut metrics: Vec<(String, Vec<Series>)> = Vec::new();
+    for (metric, labels, samples) in series {
+        let metric = metric.into();
+        match metrics.iter_mut().find(|(name, _)| *name == metric) {
+            Some((_, series)) => series.push((labels, samples)),
+            None => metrics.push((metric, vec![(labels, samples)])),
+        }
+    }
+
+    let inserts = metrics
+        .into_iter()
+        .map(|(metric, series)| metric_request(metric, series))
+        .collect();
+    RowInsertRequests { inserts }
+}
+
+/// Schema of a plain metric table, with a tag for each of `label_names`, in
+/// order. Also the schema of the `_sum` and `_count` tables of histograms and
+/// summaries.
+pub fn metric_schema(label_names: &[&str]) -> Vec<ColumnSchema> {
+    metric_schema_with(label_names, None)
+}
+
+/// Schema of a `<metric>_bucket` table, for rows from [`histogram_rows`].
+pub fn histogram_schema(label_names: &[&str]) -> Vec<ColumnSchema> {
+    metric_schema_with(label_names, Some(BUCKET_LABEL))
+}
+
+/// Schema of a summary's `<metric>` table, for rows from [`summary_rows`].
+pub fn summary_schema(label_names: &[&str]) -> Vec<ColumnSchema> {
+    metric_schema_with(label_names, Some(QUANTILE_LABEL))
+}
+
+/// A row of a plain metric table, `label_values` matching the label names of
+/// [`metric_schema`]. Use it for the `_sum` and `_count` of a histogram or
+/// summary.
+pub fn sample_row(label_values: &[&str], ts: i64, value: f64) -> Row {
+    metric_row(label_values, None, ts, value)
+}
+
+/// Expand a histogram into rows of its `<metric>_bucket` table, one per
+/// bucket, `label_values` matching the label names of [`histogram_schema`].
+///
+/// A `+Inf` bucket holding the total count is added if the histogram has
+/// none, as Prometheus always exposes one.
+pub fn histogram_rows(label_values: &[&str], ts: i64, histogram: &Histogram) -> Vec<Row> {
+    let mut rows: Vec<Row> = histogram
+        .buckets
+        .iter()
+        .map(|(bound, count)| {
+            metric_row(label_values, Some(format_bound(*bound)), ts, *count as f64)
+        })
+        .collect();
+    if !histogram
+        .buckets
+        .last()
+        .is_some_and(|(bound, _)| *bound == f64::INFINITY)
+    {
+        rows.push(metric_row(
+            label_values,
+            Some(format_bound(f64::INFINITY)),
+            ts,
+            histogram.count as f64,
+        ));
+    }
+    rows
+}
+
+/// Expand a summary into rows of its `<metric>` table, one per quantile,
+/// `label_values` matching the label names of [`summary_schema`].
+pub fn summary_rows(label_values: &[&str], ts: i64, summary: &Summary) -> Vec<Row> {
+    summary
+        .quantiles
+        .iter()
+        .map(|(quantile, value)| {
+            metric_row(label_values, Some(format_bound(*quantile)), ts, *value)
+        })
+        .collect()
+}
+
+fn metric_schema_with(label_names: &[&str], extra_label: Option<&str>) -> Vec<ColumnSchema> {
+    let mut schema: Vec<ColumnSchema> = label_names
+        .iter()
+        .chain(extra_label.as_ref())
+        .map(|name| tag(name, ColumnDataType::String))
+        .collect();
+    schema.push(timestamp(
+        TIMESTAMP_COLUMN,
+        ColumnDataType::TimestampMillisecond,
+    ));
+    schema.push(field(VALUE_COLUMN, ColumnDataType::Float64));
+    schema
+}
+
+fn metric_row(label_values: &[&str], extra_label: Option<String>, ts: i64, value: f64) -> Row {
+    let mut values: Vec<Value> = label_values
+        .iter()
+        .map(|value| string_value(value.to_string()))
+        .collect();
+    values.extend(extra_label.map(string_value));
+    values.push(timestamp_millisecond_value(ts));
+    values.push(f64_value(value));
+    Row { values }
+}
+
+/// Format a bucket bound or quantile the way Prometheus does, e.g. `0.5`, `1`
+/// or `+Inf`.
+fn format_bound(bound: f64) -> String {
+    if bound == f64::INFINITY {
+        "+Inf".to_string()
+    } else if bound == f64::NEG_INFINITY {
+        "-Inf".to_string()
+    } else {
+        bound.to_string()
+    }
+}
+
+fn metric_request(metric: String, series: Vec<Series>) -> RowInsertRequest {
+    let label_names: BTreeSet<&String> = series
+        .iter()
+        .flat_map(|(labels, _)| labels.keys())
+        .collect();
+
+    let names: Vec<&str> = label_names.iter().map(|name| name.as_str()).collect();
+    let schema = metric_schema(&names);
+
+    let mut rows = Vec::new();
+    for (labels, samples) in &series {
+        let label_values: Vec<Value> = label_names
+            .iter()
+            .map(|name| match labels.get(*name) {
+                Some(value) => string_value(value.clone()),
+                None => none_value(),
+            })
+            .collect();
+        for (ts, value) in samples {
+            let mut values = label_values.clone();
+            values.push(timestamp_millisecond_value(*ts));
+            values.push(f64_value(*value));
+            rows.push(Row { values });
+        }
+    }
+
+    RowInsertRequest {
+        table_name: metric,
+        rows: Some(Rows { schema, rows }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_prometheus_requests() {
+        let requests = prometheus_requests(vec![
+            (
+                "http_requests_total",
+                labels(&[("job", "api"), ("code", "200")]),
+                vec![(1000, 1.0), (2000, 2.0)],
+            ),
+            ("up", labels(&[("job", "api")]), vec![(1000, 1.0)]),
+            (
+                "http_requests_total",
+                labels(&[("job", "web")]),
+                vec![(1000, 5.0)],
+            ),
+        ]);
+
+        assert_eq!(2, requests.inserts.len());
+
+        let http = &requests.inserts[0];
+        assert_eq!("http_requests_total", http.table_name);
+        let rows = http.rows.as_ref().unwrap();
+        assert_eq!(
+            vec![
+                tag("code", ColumnDataType::String),
+                tag("job", ColumnDataType::String),
+                timestamp(TIMESTAMP_COLUMN, ColumnDataType::TimestampMillisecond),
+                field(VALUE_COLUMN, ColumnDataType::Float64),
+            ],
+            rows.schema
+        );
+        assert_eq!(3, rows.rows.len());
+        assert_eq!(
+            vec![
+                none_value(),
+                string_value("web".to_string()),
+                timestamp_millisecond_value(1000),
+                f64_value(5.0),
+            ],
+            rows.rows[2].values
+        );
+
+        let up = &requests.inserts[1];
+        assert_eq!("up", up.table_name);
+        assert_eq!(1, up.rows.as_ref().unwrap().rows.len());
+    }
+
+    #[test]
+    fn test_histogram_rows() {
+        let schema = histogram_schema(&["job"]);
+        assert_eq!(
+            vec![
+                tag("job", ColumnDataType::String),
+                tag(BUCKET_LABEL, ColumnDataType::String),
+                timestamp(TIMESTAMP_COLUMN, ColumnDataType::TimestampMillisecond),
+                field(VALUE_COLUMN, ColumnDataType::Float64),
+            ],
+            schema
+        );
+
+        let histogram = Histogram {
+            buckets: vec![(0.5, 2), (1.0, 3)],
+            sum: 2.5,
+            count: 4,
+        };
+        let rows = histogram_rows(&["api"], 1000, &histogram);
+        assert_eq!(3, rows.len());
+        assert!(rows.iter().all(|row| row.values.len() == schema.len()));
+        let bounds: Vec<Value> = rows.iter().map(|row| row.values[1].clone()).collect();
+        assert_eq!(
+            vec![
+                string_value("0.5".to_string()),
+                string_value("1".to_string()),
+                string_value("+Inf".to_string()),
+            ],
+            bounds
+        );
+        assert_eq!(f64_value(4.0), rows[2].values[3]);
+
+        // An explicit `+Inf` bucket isn't repeated.
+        let histogram = Histogram {
+            buckets: vec![(1.0, 3), (f64::INFINITY, 4)],
+            sum: 2.5,
+            count: 4,
+        };
+        assert_eq!(2, histogram_rows(&["api"], 1000, &histogram).len());
+
+        assert_eq!(
+            vec![
+                string_value("api".to_string()),
+                timestamp_millisecond_value(1000),
+                f64_value(2.5),
+            ],
+            sample_row(&["api"], 1000, histogram.sum).values
+        );
+    }
+
+    #[test]
+    fn test_summary_rows() {
+        let schema = summary_schema(&["job"]);
+        assert_eq!(tag(QUANTILE_LABEL, ColumnDataType::String), schema[1]);
+
+        let summary = Summary {
+            quantiles: vec![(0.5, 0.2), (0.99, 1.5)],
+            sum: 10.0,
+            count: 20,
+        };
+        let rows = summary_rows(&["api"], 1000, &summary);
+        assert_eq!(2, rows.len());
+        assert_eq!(
+            vec![
+                string_value("api".to_string()),
+                string_value("0.99".to_string()),
+                timestamp_millisecond_value(1000),
+                f64_value(1.5),
+            ],
+            rows[1].values
+        );
+        assert_eq!(3, metric_schema(&["job"]).len());
+    }
+}
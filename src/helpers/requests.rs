@@ -0,0 +1,384 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+
+use prost::Message;
+use snafu::{ensure, ResultExt};
+
+use crate::api::v1::*;
+use crate::error::{DecodeRequestSnafu, Result, SchemaConflictSnafu};
+use crate::helpers::rows::RowsBuilder;
+
+/// Encode `requests` to the protobuf bytes sent on the wire, e.g. for golden
+/// files or to queue them for later.
+pub fn encode_request(requests: &RowInsertRequests) -> Vec<u8> {
+    requests.encode_to_vec()
+}
+
+/// Decode requests encoded by [`encode_request`].
+pub fn decode_request(bytes: &[u8]) -> Result<RowInsertRequests> {
+    RowInsertRequests::decode(bytes).context(DecodeRequestSnafu)
+}
+
+/// Build the requests of several tables at once from their `(schema, rows)`,
+/// keyed by table name.
+///
+/// Tables are sorted by name, so the same data always makes the same request.
+/// Fails with [`Error::RowLengthMismatch`](crate::Error::RowLengthMismatch) if
+/// a row doesn't have a value per column of its table's schema.
+pub fn from_tables(
+    tables: HashMap<String, (Vec<ColumnSchema>, Vec<Row>)>,
+) -> Result<RowInsertRequests> {
+    let mut tables: Vec<_> = tables.into_iter().collect();
+    tables.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut inserts = Vec::with_capacity(tables.len());
+    for (table_name, (schema, rows)) in tables {
+        let rows = RowsBuilder::new(schema).build(rows)?;
+        inserts.push(RowInsertRequest {
+            table_name,
+            rows: Some(rows),
+        });
+    }
+    Ok(RowInsertRequests { inserts })
+}
+
+/// Split `requests` into requests of at most `max_rows` rows each, keeping
+/// the rows in order.
+///
+/// A table with more rows than fit is split across consecutive requests,
+/// while small tables share one. Tables without rows are kept as they are.
+/// `max_rows` of `0` is treated as `1`.
+pub fn split_requests(requests: RowInsertRequests, max_rows: usize) -> Vec<RowInsertRequests> {
+    let max_rows = max_rows.max(1);
+    let mut split = Vec::new();
+    let mut inserts = Vec::new();
+    let mut len = 0;
+    for insert in requests.inserts {
+        let Some(Rows { schema, rows }) = insert.rows else {
+            inserts.push(insert);
+            continue;
+        };
+        if rows.is_empty() {
+            inserts.push(RowInsertRequest {
+                table_name: insert.table_name,
+                rows: Some(Rows { schema, rows }),
+            });
+            continue;
+        }
+
+        let mut rows = rows.into_iter().peekable();
+        while rows.peek().is_some() {
+            if len == max_rows {
+                split.push(RowInsertRequests {
+                    inserts: std::mem::take(&mut inserts),
+                });
+                len = 0;
+            }
+            let chunk: Vec<Row> = rows.by_ref().take(max_rows - len).collect();
+            len += chunk.len();
+            inserts.push(RowInsertRequest {
+                table_name: insert.table_name.clone(),
+                rows: Some(Rows {
+                    schema: schema.clone(),
+                    rows: chunk,
+                }),
+            });
+        }
+    }
+    if !inserts.is_empty() || split.is_empty() {
+        split.push(RowInsertRequests { inserts });
+    }
+    split
+}
+
+/// Merge two `RowInsertRequests` into one, so they can be written in a single
+/// call.
+///
+/// Rows of a table present in both requests are concatenated, tables only in
+/// `b` are appended. Fails if any two inserts of the same table, in `a` or
+/// `b`, have different schemas, checked before any rows are moved.
+pub fn merge(a: RowInsertRequests, b: RowInsertRequests) -> Result<RowInsertRequests> {
+    let mut schemas: HashMap<&str, &Vec<ColumnSchema>> = HashMap::new();
+    for insert in a.inserts.iter().chain(&b.inserts) {
+        let Some(rows) = &insert.rows else {
+            continue;
+        };
+        let schema = schemas.entry(&insert.table_name).or_insert(&rows.schema);
+        ensure!(
+            **schema == rows.schema,
+            SchemaConflictSnafu {
+                table: &insert.table_name,
+            }
+        );
+    }
+
+    let mut merged = a;
+    for insert in b.inserts {
+        let existing = merged
+            .inserts
+            .iter_mut()
+            .find(|existing| existing.table_name == insert.table_name);
+        let Some(existing) = existing else {
+            merged.inserts.push(insert);
+            continue;
+        };
+        if let Some(rows) = insert.rows {
+            match &mut existing.rows {
+                Some(existing_rows) => existing_rows.rows.extend(rows.rows),
+                None => existing.rows = Some(rows),
+            }
+        }
+    }
+    Ok(merged)
+}
+
+/// Drop the columns of `rows` that are null in every row, from the schema and
+/// from each row, to save their encoding for wide, sparse tables.
+///
+/// The time index column is always kept. The server treats an omitted column
+/// as missing, so it writes the column's default value where one is declared
+/// in the table, and null otherwise. Don't prune columns with non-null
+/// defaults if the nulls must be kept.
+pub fn prune_null_columns(rows: &mut Rows) {
+    let keep: Vec<bool> = rows
+        .schema
+        .iter()
+        .enumerate()
+        .map(|(i, column)| {
+            column.semantic_type == SemanticType::Timestamp as i32
+                || rows.rows.iter().any(|row| {
+                    row.values
+                        .get(i)
+                        .is_none_or(|value| value.value_data.is_some())
+                })
+        })
+        .collect();
+    if keep.iter().all(|keep| *keep) {
+        return;
+    }
+
+    retain_columns(&mut rows.schema, &keep);
+    for row in &mut rows.rows {
+        retain_columns(&mut row.values, &keep);
+    }
+}
+
+fn retain_columns<T>(items: &mut Vec<T>, keep: &[bool]) {
+    let mut i = 0;
+    items.retain(|_| {
+        let retained = keep.get(i).copied().unwrap_or(true);
+        i += 1;
+        retained
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{
+        decode_request, encode_request, from_tables, merge, prune_null_columns, split_requests,
+    };
+    use crate::api::v1::*;
+    use crate::helpers::schema::{field, tag, timestamp};
+    use crate::helpers::values::{
+        i64_value, none_value, string_value, timestamp_millisecond_value,
+    };
+    use crate::Error;
+
+    fn request(table_name: &str, field_name: &str, ts: &[i64]) -> RowInsertRequest {
+        RowInsertRequest {
+            table_name: table_name.to_string(),
+            rows: Some(Rows {
+                schema: vec![
+                    timestamp("ts", ColumnDataType::TimestampMillisecond),
+                    field(field_name, ColumnDataType::Int64),
+                ],
+                rows: ts
+                    .iter()
+                    .map(|ts| Row {
+                        values: vec![timestamp_millisecond_value(*ts), i64_value(*ts)],
+                    })
+                    .collect(),
+            }),
+        }
+    }
+
+    #[test]
+    fn test_encode_request() {
+        let requests = RowInsertRequests {
+            inserts: vec![request("t1", "v", &[1, 2]), request("t2", "v", &[3])],
+        };
+
+        let bytes = encode_request(&requests);
+        assert_eq!(requests, decode_request(&bytes).unwrap());
+
+        assert!(matches!(
+            decode_request(&bytes[..bytes.len() - 1]),
+            Err(Error::DecodeRequest { .. })
+        ));
+    }
+
+    #[test]
+    fn test_from_tables() {
+        let mut tables = HashMap::new();
+        for insert in [request("t2", "v", &[3]), request("t1", "v", &[1, 2])] {
+            let rows = insert.rows.unwrap();
+            tables.insert(insert.table_name, (rows.schema, rows.rows));
+        }
+
+        let requests = from_tables(tables.clone()).unwrap();
+        assert_eq!(
+            vec![request("t1", "v", &[1, 2]), request("t2", "v", &[3])],
+            requests.inserts
+        );
+
+        tables.get_mut("t2").unwrap().1[0].values.pop();
+        assert!(matches!(
+            from_tables(tables),
+            Err(Error::RowLengthMismatch { row: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn test_split_requests() {
+        let ts: Vec<i64> = (0..10_000).collect();
+        let requests = RowInsertRequests {
+            inserts: vec![request("t1", "v", &ts), request("t2", "v", &[1, 2])],
+        };
+
+        let split = split_requests(requests, 3_000);
+        let lens: Vec<Vec<usize>> = split
+            .iter()
+            .map(|requests| {
+                requests
+                    .inserts
+                    .iter()
+                    .map(|insert| insert.rows.as_ref().unwrap().rows.len())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            vec![vec![3_000], vec![3_000], vec![3_000], vec![1_000, 2]],
+            lens
+        );
+        assert_eq!(
+            request("t1", "v", &[9_000]).rows.unwrap().rows[0],
+            split[3].inserts[0].rows.as_ref().unwrap().rows[0]
+        );
+        assert_eq!("t2", split[3].inserts[1].table_name);
+
+        assert_eq!(
+            vec![RowInsertRequests::default()],
+            split_requests(RowInsertRequests::default(), 10)
+        );
+    }
+
+    #[test]
+    fn test_merge() {
+        let a = RowInsertRequests {
+            inserts: vec![request("t1", "v", &[1, 2])],
+        };
+        let b = RowInsertRequests {
+            inserts: vec![request("t1", "v", &[3]), request("t2", "v", &[4])],
+        };
+
+        let merged = merge(a, b).unwrap();
+
+        assert_eq!(2, merged.inserts.len());
+        assert_eq!(request("t1", "v", &[1, 2, 3]), merged.inserts[0]);
+        assert_eq!(request("t2", "v", &[4]), merged.inserts[1]);
+    }
+
+    #[test]
+    fn test_merge_schema_conflict() {
+        let a = RowInsertRequests {
+            inserts: vec![request("t1", "v", &[1])],
+        };
+        let b = RowInsertRequests {
+            inserts: vec![request("t2", "v", &[2]), request("t1", "other", &[3])],
+        };
+
+        let err = merge(a, b).unwrap_err();
+
+        assert!(matches!(err, Error::SchemaConflict { table, .. } if table == "t1"));
+    }
+
+    #[test]
+    fn test_merge_schema_conflict_within_b() {
+        let a = RowInsertRequests {
+            inserts: vec![request("t1", "v", &[1])],
+        };
+        let b = RowInsertRequests {
+            inserts: vec![request("t2", "v", &[2]), request("t2", "other", &[3])],
+        };
+
+        let err = merge(a, b).unwrap_err();
+
+        assert!(matches!(err, Error::SchemaConflict { table, .. } if table == "t2"));
+    }
+
+    #[test]
+    fn test_prune_null_columns() {
+        let mut rows = Rows {
+            schema: vec![
+                tag("host", ColumnDataType::String),
+                timestamp("ts", ColumnDataType::TimestampMillisecond),
+                field("cpu", ColumnDataType::Int64),
+                field("memory", ColumnDataType::Int64),
+            ],
+            rows: vec![
+                Row {
+                    values: vec![none_value(), none_value(), i64_value(1), none_value()],
+                },
+                Row {
+                    values: vec![
+                        string_value("h1".to_string()),
+                        timestamp_millisecond_value(1),
+                        none_value(),
+                        none_value(),
+                    ],
+                },
+            ],
+        };
+
+        prune_null_columns(&mut rows);
+
+        assert_eq!(
+            vec![
+                tag("host", ColumnDataType::String),
+                timestamp("ts", ColumnDataType::TimestampMillisecond),
+                field("cpu", ColumnDataType::Int64),
+            ],
+            rows.schema
+        );
+        assert_eq!(
+            vec![
+                Row {
+                    values: vec![none_value(), none_value(), i64_value(1)],
+                },
+                Row {
+                    values: vec![
+                        string_value("h1".to_string()),
+                        timestamp_millisecond_value(1),
+                        none_value(),
+                    ],
+                },
+            ],
+            rows.rows
+        );
+    }
+}
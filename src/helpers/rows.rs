@@ -0,0 +1,678 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashSet;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use prost::Message;
+use snafu::{ensure, OptionExt};
+
+use crate::api::v1::value::ValueData;
+use crate::api::v1::*;
+use crate::error::{
+    FutureTimestampSnafu, IntegerWidthMismatchSnafu, MissingColumnValueSnafu,
+    NullInNonNullableColumnSnafu, Result, RowLengthMismatchSnafu, UnknownColumnDataTypeSnafu,
+    UnknownColumnSnafu, ValueTypeMismatchSnafu,
+};
+use crate::helpers::values::{datatype_of, none_value};
+
+/// Check every row of `rows` against its schema before sending it.
+///
+/// Rows must have a value per column, and non-null values must be of their
+/// column's datatype. Integer values of the wrong width, e.g. an `i32_value`
+/// in an `Int8` column, fail with
+/// [`Error::IntegerWidthMismatch`](crate::Error::IntegerWidthMismatch): the
+/// protobuf encodes 8 and 16-bit integers as 32-bit ones, so the server can't
+/// always tell them apart. Other mismatches, signedness ones included, e.g. a
+/// `u8_value` in an `Int8` column, fail with
+/// [`Error::ValueTypeMismatch`](crate::Error::ValueTypeMismatch).
+pub fn validate_rows(rows: &Rows) -> Result<()> {
+    let mut datatypes = Vec::with_capacity(rows.schema.len());
+    for column in &rows.schema {
+        let datatype =
+            ColumnDataType::try_from(column.datatype)
+                .ok()
+                .context(UnknownColumnDataTypeSnafu {
+                    datatype: column.datatype,
+                })?;
+        datatypes.push(datatype);
+    }
+
+    for (i, row) in rows.rows.iter().enumerate() {
+        ensure!(
+            row.values.len() == rows.schema.len(),
+            RowLengthMismatchSnafu {
+                row: i,
+                expected: rows.schema.len(),
+                actual: row.values.len(),
+            }
+        );
+        for ((value, column), expected) in row.values.iter().zip(&rows.schema).zip(&datatypes) {
+            let Some(value_data) = &value.value_data else {
+                continue;
+            };
+            let Some(actual) = datatype_of(value_data) else {
+                continue;
+            };
+            if actual == *expected {
+                continue;
+            }
+            let signedness = integer_signedness(actual);
+            if signedness.is_some() && signedness == integer_signedness(*expected) {
+                return IntegerWidthMismatchSnafu {
+                    column: &column.column_name,
+                    expected: *expected,
+                    actual,
+                }
+                .fail();
+            }
+            return ValueTypeMismatchSnafu {
+                column: &column.column_name,
+                expected: *expected,
+                actual,
+            }
+            .fail();
+        }
+    }
+    Ok(())
+}
+
+/// Check that no time index value of `rows` is more than `max_skew` ahead of
+/// the local clock, failing with
+/// [`Error::FutureTimestamp`](crate::Error::FutureTimestamp) otherwise.
+///
+/// Catches producers with a wrong clock before their rows land far in the
+/// future of the table. Past timestamps, such as backfilled late data, are
+/// accepted. A `max_skew` too large to add to the clock, e.g.
+/// [`Duration::MAX`], disables the check.
+pub fn check_future_timestamps(rows: &Rows, max_skew: Duration) -> Result<()> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let Some(limit) = now.checked_add(max_skew) else {
+        return Ok(());
+    };
+    let limit = i128::try_from(limit.as_nanos()).unwrap_or(i128::MAX);
+
+    for (i, column) in rows.schema.iter().enumerate() {
+        if column.semantic_type != SemanticType::Timestamp as i32 {
+            continue;
+        }
+        for (row_index, row) in rows.rows.iter().enumerate() {
+            let Some(nanos) = row
+                .values
+                .get(i)
+                .and_then(|value| value.value_data.as_ref())
+                .and_then(timestamp_nanos)
+            else {
+                continue;
+            };
+            ensure!(
+                nanos <= limit,
+                FutureTimestampSnafu {
+                    column: &column.column_name,
+                    row: row_index,
+                    max_skew,
+                }
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Which of the duplicate rows [`dedup_rows`] keeps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Keep {
+    First,
+    Last,
+}
+
+/// Remove the rows of `rows` with the same tags and timestamp as another one,
+/// keeping the first or last of them, and return the number of rows removed.
+///
+/// The server deduplicates rows by the same key on its own, so this only
+/// saves sending duplicates known at the source. Kept rows stay in their
+/// order. Rows without any tag or timestamp column have no key to compare
+/// and are kept as they are.
+pub fn dedup_rows(rows: &mut Rows, keep: Keep) -> usize {
+    let key_columns: Vec<usize> = rows
+        .schema
+        .iter()
+        .enumerate()
+        .filter(|(_, column)| {
+            column.semantic_type == SemanticType::Tag as i32
+                || column.semantic_type == SemanticType::Timestamp as i32
+        })
+        .map(|(i, _)| i)
+        .collect();
+    if key_columns.is_empty() {
+        return 0;
+    }
+    let key = |row: &Row| -> Vec<Vec<u8>> {
+        key_columns
+            .iter()
+            .map(|i| {
+                row.values
+                    .get(*i)
+                    .map(Message::encode_to_vec)
+                    .unwrap_or_default()
+            })
+            .collect()
+    };
+
+    let mut seen = HashSet::with_capacity(rows.rows.len());
+    let mut kept = vec![false; rows.rows.len()];
+    match keep {
+        Keep::First => {
+            for (i, row) in rows.rows.iter().enumerate() {
+                kept[i] = seen.insert(key(row));
+            }
+        }
+        Keep::Last => {
+            for (i, row) in rows.rows.iter().enumerate().rev() {
+                kept[i] = seen.insert(key(row));
+            }
+        }
+    }
+
+    let before = rows.rows.len();
+    let mut kept = kept.into_iter();
+    rows.rows.retain(|_| kept.next().unwrap_or(true));
+    before - rows.rows.len()
+}
+
+fn timestamp_nanos(value: &ValueData) -> Option<i128> {
+    let nanos = match value {
+        ValueData::TimestampSecondValue(v) => *v as i128 * 1_000_000_000,
+        ValueData::TimestampMillisecondValue(v) => *v as i128 * 1_000_000,
+        ValueData::TimestampMicrosecondValue(v) => *v as i128 * 1_000,
+        ValueData::TimestampNanosecondValue(v) => *v as i128,
+        _ => return None,
+    };
+    Some(nanos)
+}
+
+/// Check the rows of `request` like [`validate_rows`], and that its
+/// non-nullable columns have no null value, failing with
+/// [`Error::NullInNonNullableColumn`](crate::Error::NullInNonNullableColumn)
+/// otherwise.
+///
+/// Row schemas don't carry nullability, so the time index is the only column
+/// known to be non-nullable: the server rejects rows without a timestamp.
+pub fn validate_request(request: &RowInsertRequest) -> Result<()> {
+    let Some(rows) = &request.rows else {
+        return Ok(());
+    };
+    validate_rows(rows)?;
+
+    for (i, column) in rows.schema.iter().enumerate() {
+        if column.semantic_type != SemanticType::Timestamp as i32 {
+            continue;
+        }
+        if let Some(row_index) = rows
+            .rows
+            .iter()
+            .position(|row| row.values[i].value_data.is_none())
+        {
+            return NullInNonNullableColumnSnafu {
+                table: &request.table_name,
+                column: &column.column_name,
+                row_index,
+            }
+            .fail();
+        }
+    }
+    Ok(())
+}
+
+/// Whether `datatype` is a signed integer type, `None` if not an integer.
+fn integer_signedness(datatype: ColumnDataType) -> Option<bool> {
+    match datatype {
+        ColumnDataType::Int8
+        | ColumnDataType::Int16
+        | ColumnDataType::Int32
+        | ColumnDataType::Int64 => Some(true),
+        ColumnDataType::Uint8
+        | ColumnDataType::Uint16
+        | ColumnDataType::Uint32
+        | ColumnDataType::Uint64 => Some(false),
+        _ => None,
+    }
+}
+
+/// What [`RowsBuilder::build`] does with a row that doesn't have a value per
+/// column.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OnLengthMismatch {
+    /// Fail with [`Error::RowLengthMismatch`](crate::Error::RowLengthMismatch).
+    #[default]
+    Error,
+    /// Drop the extra values of overlong rows. Short rows still fail.
+    Truncate,
+    /// Pad short rows with `none_value()`, and drop the extra values of
+    /// overlong ones.
+    PadNull,
+}
+
+/// Holds the schema of a table to build the `Rows` of each batch written to
+/// it, so all batches share the same schema.
+#[derive(Debug, Clone)]
+pub struct RowsBuilder {
+    schema: Vec<ColumnSchema>,
+    on_length_mismatch: OnLengthMismatch,
+}
+
+impl RowsBuilder {
+    pub fn new(schema: Vec<ColumnSchema>) -> Self {
+        Self {
+            schema,
+            on_length_mismatch: OnLengthMismatch::default(),
+        }
+    }
+
+    /// Set how rows without a value per column are handled, defaults to
+    /// [`OnLengthMismatch::Error`]. Truncated rows are logged as warnings.
+    pub fn on_length_mismatch(mut self, policy: OnLengthMismatch) -> Self {
+        self.on_length_mismatch = policy;
+        self
+    }
+
+    /// The schema of the built rows, e.g. for a [`RowBuilder`].
+    pub fn schema(&self) -> &[ColumnSchema] {
+        &self.schema
+    }
+
+    /// Build a batch of `rows` with the schema. Rows that don't have a value
+    /// per column are handled as set by [`on_length_mismatch`](Self::on_length_mismatch),
+    /// failing with [`Error::RowLengthMismatch`](crate::Error::RowLengthMismatch)
+    /// by default.
+    pub fn build(&self, mut rows: Vec<Row>) -> Result<Rows> {
+        let expected = self.schema.len();
+        for (i, row) in rows.iter_mut().enumerate() {
+            let actual = row.values.len();
+            match self.on_length_mismatch {
+                OnLengthMismatch::Truncate | OnLengthMismatch::PadNull if actual > expected => {
+                    log::warn!(
+                        "Truncating row {} from {} values to the {} columns of the schema",
+                        i,
+                        actual,
+                        expected
+                    );
+                    row.values.truncate(expected);
+                }
+                OnLengthMismatch::PadNull if actual < expected => {
+                    row.values.resize(expected, none_value());
+                }
+                _ => ensure!(
+                    actual == expected,
+                    RowLengthMismatchSnafu {
+                        row: i,
+                        expected,
+                        actual,
+                    }
+                ),
+            }
+        }
+        Ok(Rows {
+            schema: self.schema.clone(),
+            rows,
+        })
+    }
+}
+
+/// Builds a `Row` from values given by column name, placed at their column's
+/// position in the schema, so rows stay right when the schema order changes:
+///
+/// ```ignore
+/// let row = RowBuilder::new(&schema)
+///     .set("city", string_value("Beijing".to_string()))
+///     .set("temperature", f32_value(26.4))
+///     .set("ts", timestamp_millisecond_value(1686109527000))
+///     .build()?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct RowBuilder<'a> {
+    schema: &'a [ColumnSchema],
+    values: Vec<Option<Value>>,
+    unknown_column: Option<String>,
+}
+
+impl<'a> RowBuilder<'a> {
+    pub fn new(schema: &'a [ColumnSchema]) -> Self {
+        Self {
+            schema,
+            values: vec![None; schema.len()],
+            unknown_column: None,
+        }
+    }
+
+    /// Set the value of `column`, replacing any value set before.
+    pub fn set(mut self, column: &str, value: Value) -> Self {
+        match self
+            .schema
+            .iter()
+            .position(|schema| schema.column_name == column)
+        {
+            Some(i) => self.values[i] = Some(value),
+            None => {
+                self.unknown_column
+                    .get_or_insert_with(|| column.to_string());
+            }
+        }
+        self
+    }
+
+    /// Build the row. Fails with [`Error::UnknownColumn`](crate::Error::UnknownColumn)
+    /// if a column not in the schema was set, or with
+    /// [`Error::MissingColumnValue`](crate::Error::MissingColumnValue) if a
+    /// column of the schema wasn't, use `none_value()` for nulls.
+    pub fn build(self) -> Result<Row> {
+        if let Some(column) = self.unknown_column {
+            return UnknownColumnSnafu { column }.fail();
+        }
+
+        let mut values = Vec::with_capacity(self.values.len());
+        for (value, schema) in self.values.into_iter().zip(self.schema) {
+            values.push(value.context(MissingColumnValueSnafu {
+                column: &schema.column_name,
+            })?);
+        }
+        Ok(Row { values })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::schema::{field, tag, timestamp};
+    use crate::helpers::values::{
+        f32_value, i32_value, i8_value, string_value, timestamp_millisecond_value, u8_value,
+    };
+    use crate::Error;
+
+    fn weather_schema() -> Vec<ColumnSchema> {
+        vec![
+            tag("city", ColumnDataType::String),
+            timestamp("ts", ColumnDataType::TimestampMillisecond),
+            field("temperature", ColumnDataType::Float32),
+        ]
+    }
+
+    #[test]
+    fn test_row_builder() {
+        let schema = weather_schema();
+        let row = RowBuilder::new(&schema)
+            .set("temperature", f32_value(26.4))
+            .set("city", string_value("Beijing".to_string()))
+            .set("ts", timestamp_millisecond_value(1686109527000))
+            .build()
+            .unwrap();
+        assert_eq!(
+            vec![
+                string_value("Beijing".to_string()),
+                timestamp_millisecond_value(1686109527000),
+                f32_value(26.4),
+            ],
+            row.values
+        );
+
+        let row = RowBuilder::new(&schema)
+            .set("city", none_value())
+            .set("ts", timestamp_millisecond_value(1))
+            .set("temperature", none_value())
+            .build()
+            .unwrap();
+        assert_eq!(none_value(), row.values[0]);
+    }
+
+    #[test]
+    fn test_row_builder_errors() {
+        let schema = weather_schema();
+
+        let err = RowBuilder::new(&schema)
+            .set("city", string_value("Beijing".to_string()))
+            .set("ts", timestamp_millisecond_value(1))
+            .build()
+            .unwrap_err();
+        assert!(
+            matches!(err, Error::MissingColumnValue { ref column, .. } if column == "temperature")
+        );
+
+        let err = RowBuilder::new(&schema)
+            .set("city", string_value("Beijing".to_string()))
+            .set("humidity", f32_value(0.5))
+            .build()
+            .unwrap_err();
+        assert!(matches!(err, Error::UnknownColumn { ref column, .. } if column == "humidity"));
+    }
+
+    #[test]
+    fn test_validate_rows() {
+        let mut rows = Rows {
+            schema: vec![
+                timestamp("ts", ColumnDataType::TimestampMillisecond),
+                field("level", ColumnDataType::Int8),
+            ],
+            rows: vec![
+                Row {
+                    values: vec![timestamp_millisecond_value(1), i8_value(3)],
+                },
+                Row {
+                    values: vec![timestamp_millisecond_value(2), none_value()],
+                },
+            ],
+        };
+        validate_rows(&rows).unwrap();
+
+        rows.rows[1].values[1] = i32_value(3);
+        let err = validate_rows(&rows).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::IntegerWidthMismatch {
+                expected: ColumnDataType::Int8,
+                actual: ColumnDataType::Int32,
+                ..
+            }
+        ));
+
+        // Same width, different signedness.
+        rows.rows[1].values[1] = u8_value(3);
+        let err = validate_rows(&rows).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::ValueTypeMismatch {
+                expected: ColumnDataType::Int8,
+                actual: ColumnDataType::Uint8,
+                ..
+            }
+        ));
+
+        rows.rows[1].values[1] = f32_value(3.0);
+        let err = validate_rows(&rows).unwrap_err();
+        assert!(matches!(err, Error::ValueTypeMismatch { ref column, .. } if column == "level"));
+
+        rows.rows[1].values.pop();
+        let err = validate_rows(&rows).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::RowLengthMismatch {
+                row: 1,
+                expected: 2,
+                actual: 1,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_check_future_timestamps() {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as i64;
+        let mut rows = Rows {
+            schema: weather_schema(),
+            rows: vec![Row {
+                values: vec![
+                    string_value("Beijing".to_string()),
+                    timestamp_millisecond_value(now - 86_400_000),
+                    f32_value(26.4),
+                ],
+            }],
+        };
+        check_future_timestamps(&rows, Duration::from_secs(60)).unwrap();
+
+        rows.rows[0].values[1] = timestamp_millisecond_value(now + 3_600_000);
+        let err = check_future_timestamps(&rows, Duration::from_secs(60)).unwrap_err();
+        assert!(matches!(err, Error::FutureTimestamp { ref column, row: 0, .. } if column == "ts"));
+        check_future_timestamps(&rows, Duration::from_secs(7_200)).unwrap();
+        check_future_timestamps(&rows, Duration::MAX).unwrap();
+    }
+
+    #[test]
+    fn test_dedup_rows() {
+        let row = |city: &str, ts: i64, temperature: f32| Row {
+            values: vec![
+                string_value(city.to_string()),
+                timestamp_millisecond_value(ts),
+                f32_value(temperature),
+            ],
+        };
+        let rows = Rows {
+            schema: weather_schema(),
+            rows: vec![
+                row("Beijing", 1, 20.0),
+                row("Shanghai", 1, 25.0),
+                row("Beijing", 1, 21.0),
+                row("Beijing", 2, 22.0),
+            ],
+        };
+
+        let mut first = rows.clone();
+        assert_eq!(1, dedup_rows(&mut first, Keep::First));
+        assert_eq!(
+            vec![
+                row("Beijing", 1, 20.0),
+                row("Shanghai", 1, 25.0),
+                row("Beijing", 2, 22.0),
+            ],
+            first.rows
+        );
+
+        let mut last = rows;
+        assert_eq!(1, dedup_rows(&mut last, Keep::Last));
+        assert_eq!(
+            vec![
+                row("Shanghai", 1, 25.0),
+                row("Beijing", 1, 21.0),
+                row("Beijing", 2, 22.0),
+            ],
+            last.rows
+        );
+    }
+
+    #[test]
+    fn test_dedup_rows_without_key_columns() {
+        let row = |temperature: f32| Row {
+            values: vec![f32_value(temperature)],
+        };
+        let mut rows = Rows {
+            schema: vec![field("temperature", ColumnDataType::Float32)],
+            rows: vec![row(21.0), row(21.0), row(22.0)],
+        };
+
+        assert_eq!(0, dedup_rows(&mut rows, Keep::Last));
+        assert_eq!(vec![row(21.0), row(21.0), row(22.0)], rows.rows);
+    }
+
+    #[test]
+    fn test_validate_request() {
+        let mut request = RowInsertRequest {
+            table_name: "weather".to_string(),
+            rows: Some(Rows {
+                schema: weather_schema(),
+                rows: vec![Row {
+                    values: vec![none_value(), timestamp_millisecond_value(1), none_value()],
+                }],
+            }),
+        };
+        validate_request(&request).unwrap();
+
+        request.rows.as_mut().unwrap().rows[0].values[1] = none_value();
+        let err = validate_request(&request).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::NullInNonNullableColumn { ref table, ref column, row_index: 0, .. }
+                if table == "weather" && column == "ts"
+        ));
+    }
+
+    #[test]
+    fn test_rows_builder() {
+        let builder = RowsBuilder::new(weather_schema());
+        assert_eq!(weather_schema(), builder.schema());
+
+        let row = RowBuilder::new(builder.schema())
+            .set("city", string_value("Beijing".to_string()))
+            .set("ts", timestamp_millisecond_value(1))
+            .set("temperature", f32_value(26.4))
+            .build()
+            .unwrap();
+        let rows = builder.build(vec![row.clone(), row.clone()]).unwrap();
+        assert_eq!(weather_schema(), rows.schema);
+        assert_eq!(vec![row.clone(), row], rows.rows);
+
+        let err = builder
+            .build(vec![Row {
+                values: vec![none_value()],
+            }])
+            .unwrap_err();
+        assert!(matches!(err, Error::RowLengthMismatch { row: 0, .. }));
+    }
+
+    #[test]
+    fn test_rows_builder_on_length_mismatch() {
+        let short = Row {
+            values: vec![string_value("Beijing".to_string())],
+        };
+        let long = Row {
+            values: vec![
+                string_value("Beijing".to_string()),
+                timestamp_millisecond_value(1),
+                f32_value(26.4),
+                f32_value(0.5),
+            ],
+        };
+
+        let builder =
+            RowsBuilder::new(weather_schema()).on_length_mismatch(OnLengthMismatch::Truncate);
+        let rows = builder.build(vec![long.clone()]).unwrap();
+        assert_eq!(&long.values[..3], &rows.rows[0].values[..]);
+        let err = builder.build(vec![short.clone()]).unwrap_err();
+        assert!(matches!(err, Error::RowLengthMismatch { actual: 1, .. }));
+
+        let builder =
+            RowsBuilder::new(weather_schema()).on_length_mismatch(OnLengthMismatch::PadNull);
+        let rows = builder.build(vec![short, long]).unwrap();
+        assert_eq!(
+            vec![
+                string_value("Beijing".to_string()),
+                none_value(),
+                none_value(),
+            ],
+            rows.rows[0].values
+        );
+        assert_eq!(3, rows.rows[1].values.len());
+    }
+}
@@ -12,7 +12,10 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use snafu::ensure;
+
 use crate::api::v1::*;
+use crate::error::{Result, UnknownColumnSnafu};
 
 pub fn tag(name: &str, datatype: ColumnDataType) -> ColumnSchema {
     ColumnSchema {
@@ -40,3 +43,66 @@ pub fn field(name: &str, datatype: ColumnDataType) -> ColumnSchema {
         ..Default::default()
     }
 }
+
+/// Build a schema from `(name, datatype)` columns, making the columns named in
+/// `tags` tags, the one named `timestamp` the time index, and the rest fields.
+///
+/// Columns keep their order. Fails with
+/// [`Error::UnknownColumn`](crate::Error::UnknownColumn) if `timestamp` or a
+/// name in `tags` is not one of the columns.
+pub fn schema_from_sets(
+    columns: &[(&str, ColumnDataType)],
+    tags: &[&str],
+    timestamp: &str,
+) -> Result<Vec<ColumnSchema>> {
+    for name in tags.iter().chain([&timestamp]) {
+        ensure!(
+            columns.iter().any(|(column, _)| column == name),
+            UnknownColumnSnafu { column: *name }
+        );
+    }
+
+    Ok(columns
+        .iter()
+        .map(|(name, datatype)| {
+            if *name == timestamp {
+                self::timestamp(name, *datatype)
+            } else if tags.contains(name) {
+                tag(name, *datatype)
+            } else {
+                field(name, *datatype)
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    #[test]
+    fn test_schema_from_sets() {
+        let columns = [
+            ("host", ColumnDataType::String),
+            ("region", ColumnDataType::String),
+            ("cpu", ColumnDataType::Float64),
+            ("ts", ColumnDataType::TimestampMillisecond),
+        ];
+        let schema = schema_from_sets(&columns, &["region", "host"], "ts").unwrap();
+        assert_eq!(
+            vec![
+                tag("host", ColumnDataType::String),
+                tag("region", ColumnDataType::String),
+                field("cpu", ColumnDataType::Float64),
+                timestamp("ts", ColumnDataType::TimestampMillisecond),
+            ],
+            schema
+        );
+
+        let err = schema_from_sets(&columns, &["zone"], "ts").unwrap_err();
+        assert!(matches!(err, Error::UnknownColumn { ref column, .. } if column == "zone"));
+        let err = schema_from_sets(&columns, &[], "time").unwrap_err();
+        assert!(matches!(err, Error::UnknownColumn { ref column, .. } if column == "time"));
+    }
+}
@@ -13,6 +13,7 @@
 // limitations under the License.
 
 use greptime_proto::v1::{Decimal128, IntervalMonthDayNano};
+use snafu::ResultExt;
 
 macro_rules! define_value_fn {
     ($fn_name:ident, $arg_type:ty, $inner_type:ident) => {
@@ -57,6 +58,25 @@ define_value_fn!(bool_value, bool, BoolValue);
 define_value_fn!(string_value, String, StringValue);
 define_value_fn!(binary_value, Vec<u8>, BinaryValue);
 
+/// Build a string value from a borrowed `&str`, copying it once.
+///
+/// The protobuf message owns its `String`, so values can't share storage
+/// with `Bytes` or `Arc<str>` buffers, but slices of a parsed buffer need no
+/// other allocation than this copy.
+#[inline]
+pub fn string_value_ref(v: &str) -> crate::api::v1::Value {
+    string_value(v.to_string())
+}
+
+/// Build a string value from bytes of untrusted text, failing with
+/// [`Error::InvalidUtf8`](crate::Error::InvalidUtf8) if they are not valid
+/// UTF-8 rather than replacing the invalid sequences like
+/// `String::from_utf8_lossy` does.
+pub fn string_value_checked(v: &[u8]) -> crate::Result<crate::api::v1::Value> {
+    let v = std::str::from_utf8(v).context(crate::error::InvalidUtf8Snafu)?;
+    Ok(string_value_ref(v))
+}
+
 define_value_fn!(date_value, i32, DateValue);
 define_value_fn!(datetime_value, i64, DatetimeValue);
 define_value_fn!(timestamp_second_value, i64, TimestampSecondValue);
@@ -87,6 +107,122 @@ pub fn interval_month_day_nano_value(
     }
 }
 
+/// Parse an ISO 8601 duration, e.g. `P1Y2M10DT2H30M` or `-PT0.5S`, into an
+/// interval value.
+///
+/// Years are 12 months and weeks 7 days, hours, minutes and seconds go to
+/// nanoseconds. Only seconds may have a fraction, of up to 9 digits. A
+/// malformed duration fails with
+/// [`Error::InvalidInterval`](crate::Error::InvalidInterval) giving the byte
+/// position of the problem.
+pub fn interval_from_iso8601(s: &str) -> crate::Result<crate::api::v1::Value> {
+    let err = |position: usize, msg: &str| {
+        crate::error::InvalidIntervalSnafu {
+            input: s,
+            position,
+            msg,
+        }
+        .build()
+    };
+
+    let bytes = s.as_bytes();
+    let negative = bytes.first() == Some(&b'-');
+    let mut pos = usize::from(negative);
+    if bytes.get(pos) != Some(&b'P') {
+        return Err(err(pos, "expect 'P'"));
+    }
+    pos += 1;
+
+    let (mut months, mut days, mut nanos) = (0i128, 0i128, 0i128);
+    let mut in_time = false;
+    let mut last_rank = 0;
+    let mut components = 0;
+    while pos < bytes.len() {
+        if bytes[pos] == b'T' {
+            if in_time {
+                return Err(err(pos, "duplicate 'T'"));
+            }
+            in_time = true;
+            pos += 1;
+            if pos == bytes.len() {
+                return Err(err(pos, "expect a time component after 'T'"));
+            }
+            continue;
+        }
+
+        let start = pos;
+        while pos < bytes.len() && (bytes[pos].is_ascii_digit() || bytes[pos] == b'.') {
+            pos += 1;
+        }
+        if start == pos {
+            return Err(err(pos, "expect a number"));
+        }
+        let number = &s[start..pos];
+        let Some(&unit) = bytes.get(pos) else {
+            return Err(err(pos, "expect a unit"));
+        };
+        let rank = match (in_time, unit) {
+            (false, b'Y') => 1,
+            (false, b'M') => 2,
+            (false, b'W') => 3,
+            (false, b'D') => 4,
+            (true, b'H') => 5,
+            (true, b'M') => 6,
+            (true, b'S') => 7,
+            _ => return Err(err(pos, "unexpected unit")),
+        };
+        if rank <= last_rank {
+            return Err(err(pos, "unit out of order"));
+        }
+        last_rank = rank;
+
+        let (int, fraction) = match number.split_once('.') {
+            Some(_) if rank != 7 => return Err(err(start, "only seconds may have a fraction")),
+            Some((int, fraction)) => (int, fraction),
+            None => (number, ""),
+        };
+        let has_fraction = number.contains('.');
+        if int.is_empty()
+            || (has_fraction && fraction.is_empty())
+            || fraction.contains('.')
+            || fraction.len() > 9
+        {
+            return Err(err(start, "malformed number"));
+        }
+        let int: i128 = int
+            .parse::<i64>()
+            .map_err(|_| err(start, "number out of range"))?
+            .into();
+        match rank {
+            1 => months += int * 12,
+            2 => months += int,
+            3 => days += int * 7,
+            4 => days += int,
+            5 => nanos += int * 3_600_000_000_000,
+            6 => nanos += int * 60_000_000_000,
+            _ => {
+                let fraction: i128 = format!("{fraction:0<9}")
+                    .parse()
+                    .map_err(|_| err(start, "malformed number"))?;
+                nanos += int * 1_000_000_000 + fraction;
+            }
+        }
+        pos += 1;
+        components += 1;
+    }
+    if components == 0 {
+        return Err(err(pos, "expect at least one component"));
+    }
+
+    let sign = if negative { -1 } else { 1 };
+    let out_of_range = || err(0, "interval out of range");
+    Ok(interval_month_day_nano_value(
+        i32::try_from(sign * months).map_err(|_| out_of_range())?,
+        i32::try_from(sign * days).map_err(|_| out_of_range())?,
+        i64::try_from(sign * nanos).map_err(|_| out_of_range())?,
+    ))
+}
+
 #[inline]
 pub fn decimal128_value(v: i128) -> crate::api::v1::Value {
     crate::api::v1::Value {
@@ -98,3 +234,280 @@ pub fn decimal128_value(v: i128) -> crate::api::v1::Value {
         )),
     }
 }
+
+/// Build a datetime value from a timezone-aware `DateTime`.
+///
+/// GreptimeDB datetimes are milliseconds since the UNIX epoch in UTC and don't
+/// keep a timezone, so `v` is normalized to UTC and its offset dropped:
+/// `2024-01-01T08:00:00+08:00` and `2024-01-01T00:00:00Z` are the same value.
+/// Use [`datetime_value`] for epochs already normalized.
+#[cfg(feature = "chrono")]
+pub fn datetime_value_tz<Tz: chrono::TimeZone>(v: chrono::DateTime<Tz>) -> crate::api::v1::Value {
+    datetime_value(v.timestamp_millis())
+}
+
+/// Encode an embedding as a vector value.
+///
+/// The vector is sent as a `BinaryValue` holding its elements as consecutive
+/// little-endian `f32`s, i.e. `4 * v.len()` bytes, which is the layout
+/// GreptimeDB stores vectors in. The column datatype enum this crate is built
+/// against has no vector type, so target a `Binary` column, or a vector column
+/// created beforehand through SQL.
+pub fn vector_value(v: Vec<f32>) -> crate::api::v1::Value {
+    binary_value(v.iter().flat_map(|x| x.to_le_bytes()).collect())
+}
+
+/// Like [`vector_value`], but checks the vector has the column's declared
+/// dimension.
+pub fn vector_value_with_dim(v: Vec<f32>, dim: usize) -> crate::Result<crate::api::v1::Value> {
+    snafu::ensure!(
+        v.len() == dim,
+        crate::error::VectorDimensionMismatchSnafu {
+            expected: dim,
+            actual: v.len(),
+        }
+    );
+    Ok(vector_value(v))
+}
+
+/// The column datatype a value's variant is meant for, `None` for variants
+/// without a known counterpart.
+pub(crate) fn datatype_of(
+    value_data: &crate::api::v1::value::ValueData,
+) -> Option<crate::api::v1::ColumnDataType> {
+    use crate::api::v1::value::ValueData;
+    use crate::api::v1::ColumnDataType;
+
+    let datatype = match value_data {
+        ValueData::I8Value(_) => ColumnDataType::Int8,
+        ValueData::I16Value(_) => ColumnDataType::Int16,
+        ValueData::I32Value(_) => ColumnDataType::Int32,
+        ValueData::I64Value(_) => ColumnDataType::Int64,
+        ValueData::U8Value(_) => ColumnDataType::Uint8,
+        ValueData::U16Value(_) => ColumnDataType::Uint16,
+        ValueData::U32Value(_) => ColumnDataType::Uint32,
+        ValueData::U64Value(_) => ColumnDataType::Uint64,
+        ValueData::F32Value(_) => ColumnDataType::Float32,
+        ValueData::F64Value(_) => ColumnDataType::Float64,
+        ValueData::BoolValue(_) => ColumnDataType::Boolean,
+        ValueData::StringValue(_) => ColumnDataType::String,
+        ValueData::BinaryValue(_) => ColumnDataType::Binary,
+        ValueData::DateValue(_) => ColumnDataType::Date,
+        ValueData::DatetimeValue(_) => ColumnDataType::Datetime,
+        ValueData::TimestampSecondValue(_) => ColumnDataType::TimestampSecond,
+        ValueData::TimestampMillisecondValue(_) => ColumnDataType::TimestampMillisecond,
+        ValueData::TimestampMicrosecondValue(_) => ColumnDataType::TimestampMicrosecond,
+        ValueData::TimestampNanosecondValue(_) => ColumnDataType::TimestampNanosecond,
+        ValueData::TimeSecondValue(_) => ColumnDataType::TimeSecond,
+        ValueData::TimeMillisecondValue(_) => ColumnDataType::TimeMillisecond,
+        ValueData::TimeMicrosecondValue(_) => ColumnDataType::TimeMicrosecond,
+        ValueData::TimeNanosecondValue(_) => ColumnDataType::TimeNanosecond,
+        ValueData::IntervalYearMonthValue(_) => ColumnDataType::IntervalYearMonth,
+        ValueData::IntervalDayTimeValue(_) => ColumnDataType::IntervalDayTime,
+        ValueData::IntervalMonthDayNanoValue(_) => ColumnDataType::IntervalMonthDayNano,
+        ValueData::Decimal128Value(_) => ColumnDataType::Decimal128,
+        _ => return None,
+    };
+    Some(datatype)
+}
+
+const DEBUG_BINARY_MAX_BYTES: usize = 16;
+
+/// Render a `Value` compactly for debugging, e.g. `timestamp(1686109527000ms)`.
+///
+/// Timestamps and times show their unit, intervals their components, decimals
+/// their unscaled 128-bit value, and binaries are truncated to their first 16
+/// bytes.
+pub fn debug_value(value: &crate::api::v1::Value) -> String {
+    use crate::api::v1::value::ValueData;
+
+    let Some(value_data) = &value.value_data else {
+        return "null".to_string();
+    };
+    match value_data {
+        ValueData::I8Value(v) => format!("i8({v})"),
+        ValueData::I16Value(v) => format!("i16({v})"),
+        ValueData::I32Value(v) => format!("i32({v})"),
+        ValueData::I64Value(v) => format!("i64({v})"),
+        ValueData::U8Value(v) => format!("u8({v})"),
+        ValueData::U16Value(v) => format!("u16({v})"),
+        ValueData::U32Value(v) => format!("u32({v})"),
+        ValueData::U64Value(v) => format!("u64({v})"),
+        ValueData::F32Value(v) => format!("f32({v})"),
+        ValueData::F64Value(v) => format!("f64({v})"),
+        ValueData::BoolValue(v) => format!("bool({v})"),
+        ValueData::StringValue(v) => format!("string({v:?})"),
+        ValueData::BinaryValue(v) => {
+            let hex: String = v
+                .iter()
+                .take(DEBUG_BINARY_MAX_BYTES)
+                .map(|b| format!("{b:02x}"))
+                .collect();
+            let ellipsis = if v.len() > DEBUG_BINARY_MAX_BYTES {
+                "..."
+            } else {
+                ""
+            };
+            format!("binary({} bytes: 0x{hex}{ellipsis})", v.len())
+        }
+        ValueData::DateValue(v) => format!("date({v} days)"),
+        ValueData::DatetimeValue(v) => format!("datetime({v}ms)"),
+        ValueData::TimestampSecondValue(v) => format!("timestamp({v}s)"),
+        ValueData::TimestampMillisecondValue(v) => format!("timestamp({v}ms)"),
+        ValueData::TimestampMicrosecondValue(v) => format!("timestamp({v}us)"),
+        ValueData::TimestampNanosecondValue(v) => format!("timestamp({v}ns)"),
+        ValueData::TimeSecondValue(v) => format!("time({v}s)"),
+        ValueData::TimeMillisecondValue(v) => format!("time({v}ms)"),
+        ValueData::TimeMicrosecondValue(v) => format!("time({v}us)"),
+        ValueData::TimeNanosecondValue(v) => format!("time({v}ns)"),
+        ValueData::IntervalYearMonthValue(v) => format!("interval({v} months)"),
+        ValueData::IntervalDayTimeValue(v) => {
+            // Days are packed in the upper 32 bits, milliseconds in the lower.
+            let days = (v >> 32) as i32;
+            let millis = *v as i32;
+            format!("interval({days} days {millis}ms)")
+        }
+        ValueData::IntervalMonthDayNanoValue(v) => format!(
+            "interval({} months {} days {}ns)",
+            v.months, v.days, v.nanoseconds
+        ),
+        ValueData::Decimal128Value(v) => {
+            let unscaled = ((v.hi as i128) << 64) | (v.lo as u64 as i128);
+            format!("decimal128({unscaled})")
+        }
+        other => format!("{other:?}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interval_from_iso8601() {
+        assert_eq!(
+            interval_month_day_nano_value(14, 10, 9_000_000_000_000),
+            interval_from_iso8601("P1Y2M10DT2H30M").unwrap()
+        );
+        assert_eq!(
+            interval_month_day_nano_value(0, 14, 0),
+            interval_from_iso8601("P2W").unwrap()
+        );
+        assert_eq!(
+            interval_month_day_nano_value(0, 0, -500_000_000),
+            interval_from_iso8601("-PT0.5S").unwrap()
+        );
+        assert_eq!(
+            interval_month_day_nano_value(1, 0, 60_000_000_000),
+            interval_from_iso8601("P1MT1M").unwrap()
+        );
+
+        for (input, expected) in [
+            ("1Y", 0),
+            ("P", 1),
+            ("PT", 2),
+            ("P1", 2),
+            ("P1X", 2),
+            ("P1D2Y", 4),
+            ("P1.5D", 1),
+            ("PT1H2H", 5),
+        ] {
+            let err = interval_from_iso8601(input).unwrap_err();
+            assert!(
+                matches!(err, crate::Error::InvalidInterval { position, .. } if position == expected),
+                "{input}: {err}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_string_value_checked() {
+        assert_eq!(
+            string_value("温度".to_string()),
+            string_value_checked("温度".as_bytes()).unwrap()
+        );
+        assert!(matches!(
+            string_value_checked(&[0x66, 0xff, 0x6f]),
+            Err(crate::Error::InvalidUtf8 { .. })
+        ));
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_datetime_value_tz() {
+        use chrono::{FixedOffset, TimeZone, Utc};
+
+        let utc = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let shanghai = FixedOffset::east_opt(8 * 3600)
+            .unwrap()
+            .with_ymd_and_hms(2024, 1, 1, 8, 0, 0)
+            .unwrap();
+        assert_eq!(datetime_value(1704067200000), datetime_value_tz(utc));
+        assert_eq!(datetime_value(1704067200000), datetime_value_tz(shanghai));
+    }
+
+    #[test]
+    fn test_string_value_ref() {
+        let buf = String::from("host=127.0.0.1");
+        assert_eq!(
+            string_value("127.0.0.1".to_string()),
+            string_value_ref(&buf[5..])
+        );
+    }
+
+    #[test]
+    fn test_vector_value() {
+        assert_eq!(
+            binary_value(vec![0x00, 0x00, 0x80, 0x3f, 0x00, 0x00, 0x00, 0xc0]),
+            vector_value(vec![1.0, -2.0])
+        );
+        assert_eq!(binary_value(vec![]), vector_value(vec![]));
+
+        assert_eq!(
+            vector_value(vec![1.0, 2.0, 3.0]),
+            vector_value_with_dim(vec![1.0, 2.0, 3.0], 3).unwrap()
+        );
+        assert!(matches!(
+            vector_value_with_dim(vec![1.0, 2.0], 3),
+            Err(crate::Error::VectorDimensionMismatch {
+                expected: 3,
+                actual: 2,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_debug_value() {
+        assert_eq!("null", debug_value(&none_value()));
+        assert_eq!("i8(-3)", debug_value(&i8_value(-3)));
+        assert_eq!(
+            "string(\"c1\")",
+            debug_value(&string_value("c1".to_string()))
+        );
+        assert_eq!(
+            "timestamp(1686109527000ms)",
+            debug_value(&timestamp_millisecond_value(1686109527000))
+        );
+        assert_eq!(
+            "interval(1 months 2 days 3ns)",
+            debug_value(&interval_month_day_nano_value(1, 2, 3))
+        );
+        assert_eq!(
+            "interval(2 days 500ms)",
+            debug_value(&interval_day_time_value((2 << 32) | 500))
+        );
+        assert_eq!(
+            "decimal128(-123456789012345678901234)",
+            debug_value(&decimal128_value(-123456789012345678901234))
+        );
+        assert_eq!(
+            "binary(3 bytes: 0x01ab02)",
+            debug_value(&binary_value(vec![0x01, 0xab, 0x02]))
+        );
+        assert_eq!(
+            "binary(20 bytes: 0x00000000000000000000000000000000...)",
+            debug_value(&binary_value(vec![0; 20]))
+        );
+    }
+}
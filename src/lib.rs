@@ -15,16 +15,25 @@
 pub mod api;
 pub mod channel_manager;
 mod client;
+#[cfg(feature = "serde")]
+mod config;
 mod database;
 mod error;
 pub mod helpers;
 pub mod load_balance;
+mod rate_limit;
 mod stream_insert;
 
 pub use self::channel_manager::{ChannelConfig, ChannelManager, ClientTlsOption};
-pub use self::client::{Client, ClientBuilder, Compression};
-pub use self::database::Database;
+pub use self::client::{Client, ClientBuilder, Compression, PeerHealth, PeerStatus};
+#[cfg(feature = "serde")]
+pub use self::config::ClientConfig;
+pub use self::database::{
+    aggregate_results, parse_dbname, BatchSummary, Database, OnBatchError, TableWriter,
+    WriteSummary,
+};
 pub use self::error::{Error, Result};
-pub use self::stream_insert::StreamInserter;
+pub use self::stream_insert::{FinishSummary, StreamInserter, StreamInserterHandle};
 
+pub const DEFAULT_CATALOG_NAME: &str = "greptime";
 pub const DEFAULT_SCHEMA_NAME: &str = "public";
@@ -12,18 +12,36 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::time::Duration;
+
 use enum_dispatch::enum_dispatch;
 use rand::seq::SliceRandom;
 
+use crate::Error;
+
 #[enum_dispatch]
 pub trait LoadBalance {
     fn get_peer<'a>(&self, peers: &'a [String]) -> Option<&'a String>;
+
+    /// Feedback on a request sent to `peer`, called by the [`Client`](crate::Client)
+    /// once the request completes. Unary requests and health checks are
+    /// reported, streaming inserts are not.
+    ///
+    /// Adaptive strategies can use it to track failing or slow peers. The
+    /// default implementation ignores it.
+    fn record_result(&self, _peer: &str, _result: Result<(), &Error>) {}
+
+    /// A round-trip time measured to `peer` by a ping, e.g.
+    /// [`Database::ping`](crate::Database::ping). Ignored by default.
+    fn record_latency(&self, _peer: &str, _latency: Duration) {}
 }
 
 #[enum_dispatch(LoadBalance)]
 #[derive(Debug, Clone)]
 pub enum Loadbalancer {
     Random,
+    #[cfg(test)]
+    Counting,
 }
 
 impl Default for Loadbalancer {
@@ -41,6 +59,32 @@ impl LoadBalance for Random {
     }
 }
 
+/// Picks the first peer and counts the feedback it gets, to test what the
+/// client reports.
+#[cfg(test)]
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Counting {
+    pub(crate) results: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    pub(crate) latencies: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+}
+
+#[cfg(test)]
+impl LoadBalance for Counting {
+    fn get_peer<'a>(&self, peers: &'a [String]) -> Option<&'a String> {
+        peers.first()
+    }
+
+    fn record_result(&self, _peer: &str, _result: Result<(), &Error>) {
+        self.results
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    fn record_latency(&self, _peer: &str, _latency: Duration) {
+        self.latencies
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashSet;
@@ -0,0 +1,229 @@
+// Copyright 2023 Greptime Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use snafu::OptionExt;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::error::{Result, TooManyRequestsSnafu};
+
+/// A token bucket limiting the rate of outgoing write requests.
+///
+/// The bucket holds up to one second worth of requests, so bursts up to the
+/// rate are let through at once. Without a rate, requests are not limited.
+#[derive(Debug, Default)]
+pub(crate) struct RateLimiter {
+    bucket: Mutex<Option<Bucket>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    requests_per_sec: u32,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl Bucket {
+    fn new(requests_per_sec: u32) -> Self {
+        Self {
+            requests_per_sec,
+            tokens: requests_per_sec as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Take a token, or tell how long to wait until one is available.
+    fn try_acquire(&mut self) -> Option<Duration> {
+        let now = Instant::now();
+        let rate = self.requests_per_sec as f64;
+        let refilled = now.duration_since(self.last_refill).as_secs_f64() * rate;
+        self.tokens = (self.tokens + refilled).min(rate);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            None
+        } else {
+            Some(Duration::from_secs_f64((1.0 - self.tokens) / rate))
+        }
+    }
+}
+
+impl RateLimiter {
+    pub(crate) fn new(requests_per_sec: Option<u32>) -> Self {
+        let limiter = Self::default();
+        limiter.set_rate(requests_per_sec);
+        limiter
+    }
+
+    /// Change the rate, `None` or `0` removing the limit.
+    pub(crate) fn set_rate(&self, requests_per_sec: Option<u32>) {
+        *self.bucket.lock() = requests_per_sec.filter(|rate| *rate > 0).map(Bucket::new);
+    }
+
+    pub(crate) fn rate(&self) -> Option<u32> {
+        self.bucket
+            .lock()
+            .as_ref()
+            .map(|bucket| bucket.requests_per_sec)
+    }
+
+    /// Wait until the rate allows one more request.
+    pub(crate) async fn acquire(&self) {
+        loop {
+            let wait = self.bucket.lock().as_mut().and_then(Bucket::try_acquire);
+            match wait {
+                Some(wait) => tokio::time::sleep(wait).await,
+                None => return,
+            }
+        }
+    }
+}
+
+/// Bounds the number of requests in flight, and counts them.
+#[derive(Debug, Default)]
+pub(crate) struct ConcurrencyLimiter {
+    limit: Option<(usize, Arc<Semaphore>)>,
+    fail_fast: bool,
+    in_flight: AtomicUsize,
+}
+
+/// A request in flight, until dropped.
+pub(crate) struct InFlight<'a> {
+    _permit: Option<OwnedSemaphorePermit>,
+    in_flight: &'a AtomicUsize,
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl ConcurrencyLimiter {
+    /// `None` or `0` means no limit.
+    pub(crate) fn new(max_concurrent_requests: Option<usize>, fail_fast: bool) -> Self {
+        Self {
+            limit: max_concurrent_requests
+                .filter(|max| *max > 0)
+                .map(|max| (max, Arc::new(Semaphore::new(max)))),
+            fail_fast,
+            in_flight: AtomicUsize::new(0),
+        }
+    }
+
+    /// Wait for a request to be allowed in flight, or fail right away when
+    /// the limit is reached in fail-fast mode.
+    pub(crate) async fn acquire(&self) -> Result<InFlight<'_>> {
+        let permit = match &self.limit {
+            Some((limit, semaphore)) if self.fail_fast => Some(
+                semaphore
+                    .clone()
+                    .try_acquire_owned()
+                    .ok()
+                    .context(TooManyRequestsSnafu { limit: *limit })?,
+            ),
+            Some((_, semaphore)) => Some(
+                semaphore
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .expect("concurrency semaphore is never closed"),
+            ),
+            None => None,
+        };
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        Ok(InFlight {
+            _permit: permit,
+            in_flight: &self.in_flight,
+        })
+    }
+
+    pub(crate) fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{Bucket, ConcurrencyLimiter, RateLimiter};
+    use crate::Error;
+
+    #[tokio::test]
+    async fn test_concurrency_limiter() {
+        let limiter = ConcurrencyLimiter::new(Some(2), false);
+        let first = limiter.acquire().await.unwrap();
+        let _second = limiter.acquire().await.unwrap();
+        assert_eq!(2, limiter.in_flight());
+        assert!(
+            tokio::time::timeout(Duration::from_millis(50), limiter.acquire())
+                .await
+                .is_err()
+        );
+
+        drop(first);
+        assert_eq!(1, limiter.in_flight());
+        let _third = limiter.acquire().await.unwrap();
+
+        let limiter = ConcurrencyLimiter::new(Some(1), true);
+        let _first = limiter.acquire().await.unwrap();
+        assert!(matches!(
+            limiter.acquire().await,
+            Err(Error::TooManyRequests { limit: 1, .. })
+        ));
+        assert_eq!(1, limiter.in_flight());
+
+        // A limit of 0 means no limit, as for the rate limiter.
+        let limiter = ConcurrencyLimiter::new(Some(0), true);
+        let _first = limiter.acquire().await.unwrap();
+        let _second = limiter.acquire().await.unwrap();
+        assert_eq!(2, limiter.in_flight());
+    }
+
+    #[test]
+    fn test_bucket() {
+        let mut bucket = Bucket::new(2);
+        assert!(bucket.try_acquire().is_none());
+        assert!(bucket.try_acquire().is_none());
+        let wait = bucket.try_acquire().unwrap();
+        assert!(wait <= Duration::from_millis(500));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limiter() {
+        let limiter = RateLimiter::new(Some(20));
+        assert_eq!(Some(20), limiter.rate());
+
+        let start = Instant::now();
+        for _ in 0..21 {
+            limiter.acquire().await;
+        }
+        // The burst of 20 passes, the 21st waits for a refill.
+        assert!(start.elapsed() >= Duration::from_millis(25));
+
+        limiter.set_rate(None);
+        assert_eq!(None, limiter.rate());
+        let start = Instant::now();
+        for _ in 0..1000 {
+            limiter.acquire().await;
+        }
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -12,24 +12,45 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::error::Result;
+use crate::database::{check_message_size, insert_request_id, is_empty};
 use crate::error::{self, IllegalDatabaseResponseSnafu};
+use crate::error::{Error, Result};
+use crate::helpers::requests::split_requests;
+use crate::rate_limit::RateLimiter;
+use futures::{Sink, Stream, StreamExt};
 use greptime_proto::v1::greptime_request::Request;
 use greptime_proto::v1::{
-    greptime_database_client::GreptimeDatabaseClient, InsertRequest, RowInsertRequests,
+    greptime_database_client::GreptimeDatabaseClient, ColumnSchema, InsertRequest, Row,
+    RowInsertRequest, RowInsertRequests, Rows,
 };
 use greptime_proto::v1::{
     greptime_response, AffectedRows, AuthHeader, GreptimeRequest, GreptimeResponse, InsertRequests,
     RequestHeader,
 };
+use parking_lot::Mutex;
+use prost::Message;
 use snafu::OptionExt;
-use tokio::sync::mpsc;
+use std::collections::HashMap;
+use std::pin::{pin, Pin};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
 use tokio::task::JoinHandle;
 use tokio_stream::wrappers::ReceiverStream;
-use tonic::metadata::{Ascii, MetadataValue};
+use tokio_util::sync::PollSender;
+use tonic::metadata::MetadataMap;
 use tonic::transport::Channel;
 use tonic::{Response, Status};
 
+const WRITE_STREAM_BATCH_SIZE: usize = 1024;
+
+/// A request waiting in the send queue, with its share of the buffer budget.
+type QueuedRequest = (GreptimeRequest, Option<OwnedSemaphorePermit>);
+
+/// Called with the highest source offset acknowledged by the server.
+type CommitCallback = Box<dyn Fn(u64) + Send + Sync>;
+
 /// A structure that provides some methods for streaming data insert.
 ///
 /// [`StreamInserter`] cannot be constructed via the `StreamInserter::new` method.
@@ -43,14 +64,180 @@ use tonic::{Response, Status};
 ///
 /// If you want to see a concrete usage example, please see
 /// [stream_inserter.rs](https://github.com/GreptimeTeam/greptimedb-client-rust/tree/master/examples/stream_ingest.rs).
+///
+/// [`StreamInserter`] is also a [`Sink`] of [`RowInsertRequests`], so it can be
+/// fed with stream combinators such as `forward`. Closing the sink only stops
+/// accepting requests, call [`StreamInserter::finish`] to get the rows written.
+/// Unlike the `row_insert` methods, sends through the sink are not subject to
+/// the client's [rate limit](crate::ClientBuilder::rate_limit).
 pub struct StreamInserter {
-    sender: mpsc::Sender<GreptimeRequest>,
+    handle: StreamInserterHandle,
+
+    poll_sender: PollSender<QueuedRequest>,
+
+    request_id: String,
+
+    join: AbortOnDrop<std::result::Result<Response<GreptimeResponse>, Status>>,
+
+    on_commit: Option<CommitCallback>,
+}
+
+/// A cheap, cloneable handle to send requests into a [`StreamInserter`]'s
+/// stream, obtained with [`StreamInserter::handle`].
+///
+/// Handles let multiple producers push concurrently, while the owning
+/// [`StreamInserter`] finalizes the stream.
+#[derive(Clone, Debug)]
+pub struct StreamInserterHandle {
+    sender: mpsc::Sender<QueuedRequest>,
 
     auth_header: Option<AuthHeader>,
 
     dbname: String,
 
-    join: JoinHandle<std::result::Result<Response<GreptimeResponse>, Status>>,
+    send_errors: Arc<Mutex<Vec<Error>>>,
+
+    rate_limiter: Arc<RateLimiter>,
+
+    buffer_budget: Option<Arc<BufferBudget>>,
+
+    max_message_size: Option<usize>,
+
+    /// The highest offset of the requests queued with one.
+    highest_offset: Arc<Mutex<Option<u64>>>,
+
+    skip_empty_requests: bool,
+
+    timezone: Option<String>,
+
+    max_rows_per_request: Option<usize>,
+}
+
+impl StreamInserterHandle {
+    /// Write Row based insert requests to GreptimeDB with streaming
+    pub async fn row_insert(&self, requests: RowInsertRequests) -> Result<()> {
+        if self.skip_empty_requests && is_empty(&requests) {
+            return Ok(());
+        }
+        let Some(max_rows) = self.max_rows_per_request else {
+            return self.send(Request::RowInserts(requests)).await;
+        };
+        for requests in split_requests(requests, max_rows) {
+            self.send(Request::RowInserts(requests)).await?;
+        }
+        Ok(())
+    }
+
+    /// Like [`row_insert`](Self::row_insert), also tracking the `offset` of
+    /// the rows in their source for [`StreamInserter::on_commit`].
+    pub async fn row_insert_with_offset(
+        &self,
+        requests: RowInsertRequests,
+        offset: u64,
+    ) -> Result<()> {
+        self.row_insert(requests).await?;
+        let mut highest = self.highest_offset.lock();
+        *highest = Some(highest.map_or(offset, |highest| highest.max(offset)));
+        Ok(())
+    }
+
+    async fn send(&self, request: Request) -> Result<()> {
+        let request = self.to_rpc_request(request);
+        check_message_size(&request, self.max_message_size)?;
+        self.rate_limiter.acquire().await;
+        let permit = match &self.buffer_budget {
+            Some(budget) => Some(budget.acquire(request.encoded_len()).await),
+            None => None,
+        };
+
+        self.sender
+            .send((request, permit))
+            .await
+            .map_err(|e| self.send_failed(e.to_string()))
+    }
+
+    /// Build the error of a failed send, keeping a copy for
+    /// [`StreamInserter::try_finish`].
+    fn send_failed(&self, err_msg: String) -> Error {
+        self.send_errors.lock().push(
+            error::ClientStreamingSnafu {
+                err_msg: err_msg.clone(),
+            }
+            .build(),
+        );
+        error::ClientStreamingSnafu { err_msg }.build()
+    }
+
+    fn to_rpc_request(&self, request: Request) -> GreptimeRequest {
+        GreptimeRequest {
+            header: Some(RequestHeader {
+                authorization: self.auth_header.clone(),
+                dbname: self.dbname.clone(),
+                timezone: self.timezone.clone().unwrap_or_default(),
+                ..Default::default()
+            }),
+            request: Some(request),
+        }
+    }
+}
+
+/// Bounds the encoded bytes of the requests in the send queue.
+#[derive(Debug)]
+struct BufferBudget {
+    semaphore: Arc<Semaphore>,
+    max_bytes: u32,
+}
+
+impl BufferBudget {
+    /// A budget of `max_bytes` clamped to `u32::MAX`, `None` for `0`, which
+    /// means no limit.
+    fn new(max_bytes: usize) -> Option<Self> {
+        if max_bytes == 0 {
+            return None;
+        }
+        let max_bytes = max_bytes.min(u32::MAX as usize) as u32;
+        Some(Self {
+            semaphore: Arc::new(Semaphore::new(max_bytes as usize)),
+            max_bytes,
+        })
+    }
+
+    /// Wait until `bytes` fit in the budget. A request larger than the whole
+    /// budget waits for the queue to be empty instead.
+    async fn acquire(&self, bytes: usize) -> OwnedSemaphorePermit {
+        let permits = bytes.min(self.max_bytes as usize) as u32;
+        self.semaphore
+            .clone()
+            .acquire_many_owned(permits)
+            .await
+            .expect("buffer budget semaphore is never closed")
+    }
+}
+
+/// The outcome of [`StreamInserter::try_finish`].
+#[derive(Debug)]
+pub struct FinishSummary {
+    /// The rows written as reported by the server, or the error that ended
+    /// the stream.
+    pub affected_rows: Result<u32>,
+    /// The errors of sends that failed, in the order they happened. Sends
+    /// only fail once the stream is dead, so the first one marks where rows
+    /// started being dropped, not a request skipped by a live stream.
+    pub send_errors: Vec<Error>,
+}
+
+/// Aborts the streaming task when dropped, so an inserter dropped without
+/// being finished doesn't leak it, and warns about it since the rows still
+/// queued are lost.
+struct AbortOnDrop<T>(JoinHandle<T>);
+
+impl<T> Drop for AbortOnDrop<T> {
+    fn drop(&mut self) {
+        if !self.0.is_finished() {
+            log::warn!("Aborting an unfinished streaming insert, queued rows are dropped");
+        }
+        self.0.abort();
+    }
 }
 
 impl StreamInserter {
@@ -59,57 +246,258 @@ impl StreamInserter {
         dbname: String,
         auth_header: Option<AuthHeader>,
         channel_size: usize,
-        hint: Option<MetadataValue<Ascii>>,
+        metadata: MetadataMap,
+        rate_limiter: Arc<RateLimiter>,
+        max_message_size: Option<usize>,
     ) -> Result<StreamInserter> {
         let (send, recv) = mpsc::channel(channel_size);
 
+        // A request leaves the budget once the stream hands it to the server.
+        let recv_stream = ReceiverStream::new(recv).map(|(request, _permit)| request);
+        let mut request = tonic::Request::new(recv_stream);
+        *request.metadata_mut() = metadata;
+        let request_id = insert_request_id(&mut request)?;
+
         let join: JoinHandle<std::result::Result<Response<GreptimeResponse>, Status>> =
-            tokio::spawn(async move {
-                let recv_stream = ReceiverStream::new(recv);
-                let mut request = tonic::Request::new(recv_stream);
-                if let Some(hint) = hint {
-                    request.metadata_mut().insert("x-greptime-hints", hint);
-                }
-                client.handle_requests(request).await
-            });
+            tokio::spawn(async move { client.handle_requests(request).await });
 
         Ok(StreamInserter {
-            sender: send,
-            auth_header,
-            dbname,
-            join,
+            poll_sender: PollSender::new(send.clone()),
+            handle: StreamInserterHandle {
+                sender: send,
+                auth_header,
+                dbname,
+                send_errors: Arc::default(),
+                rate_limiter,
+                buffer_budget: None,
+                max_message_size,
+                highest_offset: Arc::default(),
+                skip_empty_requests: false,
+                timezone: None,
+                max_rows_per_request: None,
+            },
+            request_id,
+            join: AbortOnDrop(join),
+            on_commit: None,
         })
     }
 
+    /// Drop row inserts without any row instead of streaming them, see
+    /// [`ClientBuilder::send_empty_requests`](crate::ClientBuilder::send_empty_requests).
+    pub(crate) fn skip_empty_requests(mut self, enabled: bool) -> Self {
+        self.handle.skip_empty_requests = enabled;
+        self
+    }
+
+    /// Set the timezone of the stream's requests, see
+    /// [`Database::with_timezone`](crate::Database::with_timezone).
+    pub(crate) fn timezone(mut self, timezone: Option<String>) -> Self {
+        self.handle.timezone = timezone;
+        self
+    }
+
+    /// Call `on_commit` with the highest offset written with
+    /// [`row_insert_with_offset`](Self::row_insert_with_offset) once the
+    /// server acknowledges it, e.g. to commit a message queue consumer's
+    /// position for at-least-once delivery.
+    ///
+    /// The server acknowledges a stream as a whole when it is
+    /// [finished](StreamInserter::finish), so the callback fires once, after
+    /// a successful finish, and not at all if the stream fails. Consumers
+    /// wanting finer-grained commits should finish a stream per batch of
+    /// offsets.
+    pub fn on_commit(mut self, on_commit: impl Fn(u64) + Send + Sync + 'static) -> Self {
+        self.on_commit = Some(Box::new(on_commit));
+        self
+    }
+
+    /// Cap the encoded size of the requests buffered in the send queue to
+    /// `bytes`, on top of the bound on their count.
+    ///
+    /// Sends wait while the budget is exceeded, so producers are held back
+    /// when rows are large even though the queue isn't full. Requests sent
+    /// through the [`Sink`] implementation are not accounted. Set the budget
+    /// before taking [handles](StreamInserter::handle), which keep the budget
+    /// they were created with.
+    ///
+    /// `0` means no limit, and budgets over `u32::MAX` bytes, about 4GiB, are
+    /// clamped to it.
+    pub fn max_buffered_bytes(mut self, bytes: usize) -> Self {
+        self.handle.buffer_budget = BufferBudget::new(bytes).map(Arc::new);
+        self
+    }
+
+    /// Split row inserts of more than `n` rows into several requests of at
+    /// most `n` rows, streamed one after another.
+    ///
+    /// Keeps request frames predictable for flow control and under the
+    /// message size limit however large a single `row_insert` is. Requests
+    /// sent through the [`Sink`] implementation are not split. Like
+    /// [`max_buffered_bytes`](Self::max_buffered_bytes), set it before taking
+    /// [handles](StreamInserter::handle).
+    pub fn max_rows_per_request(mut self, n: usize) -> Self {
+        self.handle.max_rows_per_request = Some(n);
+        self
+    }
+
     #[deprecated(note = "Use row_insert instead.")]
     pub async fn insert(&self, requests: Vec<InsertRequest>) -> Result<()> {
         let inserts = InsertRequests { inserts: requests };
-        let request = self.to_rpc_request(Request::Inserts(inserts));
-
-        self.sender.send(request).await.map_err(|e| {
-            error::ClientStreamingSnafu {
-                err_msg: e.to_string(),
-            }
-            .build()
-        })
+        self.handle.send(Request::Inserts(inserts)).await
     }
 
     /// Write Row based insert requests to GreptimeDB with streaming
     pub async fn row_insert(&self, requests: RowInsertRequests) -> Result<()> {
-        let request = self.to_rpc_request(Request::RowInserts(requests));
+        self.handle.row_insert(requests).await
+    }
 
-        self.sender.send(request).await.map_err(|e| {
-            error::ClientStreamingSnafu {
-                err_msg: e.to_string(),
+    /// Like [`row_insert`](Self::row_insert), also tracking the `offset` of
+    /// the rows in their source for [`on_commit`](Self::on_commit).
+    pub async fn row_insert_with_offset(
+        &self,
+        requests: RowInsertRequests,
+        offset: u64,
+    ) -> Result<()> {
+        self.handle.row_insert_with_offset(requests, offset).await
+    }
+
+    /// Get a cloneable handle to write into this stream from other tasks.
+    ///
+    /// [`StreamInserter::finish`] waits for the server response only once all
+    /// handles are dropped.
+    pub fn handle(&self) -> StreamInserterHandle {
+        self.handle.clone()
+    }
+
+    /// Drive `stream` to completion, writing each `(table, row)` item with
+    /// streaming.
+    ///
+    /// Items that are ready together are grouped per table into a single
+    /// request of at most `1024` rows, using the table's schema from `schemas`.
+    /// Sending awaits queue capacity, so a slow server applies backpressure to
+    /// the stream. Stops at the first item whose table has no schema, or at the
+    /// first failed send.
+    pub async fn write_stream<S>(
+        &self,
+        schemas: &HashMap<String, Vec<ColumnSchema>>,
+        stream: S,
+    ) -> Result<()>
+    where
+        S: Stream<Item = (String, Row)>,
+    {
+        let mut batches = pin!(stream.ready_chunks(WRITE_STREAM_BATCH_SIZE));
+        while let Some(batch) = batches.next().await {
+            let mut tables: Vec<(String, Vec<Row>)> = Vec::new();
+            for (table, row) in batch {
+                match tables.iter_mut().find(|(name, _)| *name == table) {
+                    Some((_, rows)) => rows.push(row),
+                    None => tables.push((table, vec![row])),
+                }
             }
-            .build()
-        })
+
+            let mut inserts = Vec::with_capacity(tables.len());
+            for (table_name, rows) in tables {
+                let schema = schemas
+                    .get(&table_name)
+                    .context(error::MissingTableSchemaSnafu { table: &table_name })?
+                    .clone();
+                inserts.push(RowInsertRequest {
+                    table_name,
+                    rows: Some(Rows { schema, rows }),
+                });
+            }
+
+            self.row_insert(RowInsertRequests { inserts }).await?;
+        }
+        Ok(())
+    }
+
+    /// Number of requests currently buffered in the send queue, waiting to be
+    /// streamed to the server.
+    pub fn queue_len(&self) -> usize {
+        self.handle.sender.max_capacity() - self.handle.sender.capacity()
+    }
+
+    /// Maximum number of requests the send queue can buffer, i.e. the
+    /// `channel_size` this inserter was created with.
+    pub fn queue_capacity(&self) -> usize {
+        self.handle.sender.max_capacity()
     }
 
+    /// The client-generated id of this streaming session, sent to the server
+    /// for correlation with its logs.
+    pub fn request_id(&self) -> &str {
+        &self.request_id
+    }
+
+    /// Close the stream and wait for the server to report the rows written.
+    ///
+    /// Dropping the inserter without finishing it aborts the stream instead.
     pub async fn finish(self) -> Result<u32> {
-        drop(self.sender);
+        self.wait_response(None).await
+    }
+
+    /// Like [`StreamInserter::finish`], but waits at most `timeout` for the
+    /// server response. On timeout the stream is aborted and
+    /// [`Error::Timeout`] is returned.
+    pub async fn finish_with_timeout(self, timeout: Duration) -> Result<u32> {
+        self.wait_response(Some(timeout)).await
+    }
+
+    /// Like [`StreamInserter::finish`], but also reports the errors of all
+    /// sends that failed on this stream, including through its handles and
+    /// its [`Sink`] implementation.
+    ///
+    /// A send only fails once the streaming task is gone, so any entry in
+    /// `send_errors` means the stream died: every later send was dropped too,
+    /// and `affected_rows` holds the error that ended it.
+    pub async fn try_finish(self) -> FinishSummary {
+        let send_errors = self.handle.send_errors.clone();
+        let affected_rows = self.wait_response(None).await;
+        let send_errors = std::mem::take(&mut *send_errors.lock());
+        FinishSummary {
+            affected_rows,
+            send_errors,
+        }
+    }
 
-        let response = self.join.await.unwrap()?;
+    /// Cancel the stream immediately, without waiting for the server.
+    ///
+    /// Unlike [`StreamInserter::finish`], which closes the stream and waits
+    /// for the server to acknowledge it, this aborts the streaming task, e.g.
+    /// on an abnormal shutdown with a possibly hung server. Requests still
+    /// queued are dropped, and the server may or may not have written the
+    /// ones already streamed. The server only acknowledges a stream once
+    /// closed, so this returns the rows written only if it had already
+    /// responded, `None` otherwise. The [`on_commit`](Self::on_commit)
+    /// callback is not called.
+    pub async fn abort(mut self) -> Option<u32> {
+        drop(self.handle);
+        drop(self.poll_sender);
+
+        let join = &mut self.join.0;
+        join.abort();
+        let response = join.await.ok()?.ok()?.into_inner().response?;
+        let greptime_response::Response::AffectedRows(AffectedRows { value }) = response;
+        Some(value)
+    }
+
+    async fn wait_response(mut self, timeout: Option<Duration>) -> Result<u32> {
+        let highest_offset = self.handle.highest_offset.clone();
+        drop(self.handle);
+        drop(self.poll_sender);
+
+        let join = &mut self.join.0;
+        let response = match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, join)
+                .await
+                .ok()
+                .context(error::TimeoutSnafu { timeout })?,
+            None => join.await,
+        };
+        let response = response
+            .unwrap()
+            .map_err(|e| Error::from(e).with_request_id(&self.request_id))?;
 
         let response = response
             .into_inner()
@@ -120,17 +508,72 @@ impl StreamInserter {
 
         let greptime_response::Response::AffectedRows(AffectedRows { value }) = response;
 
+        if let (Some(on_commit), Some(offset)) = (&self.on_commit, *highest_offset.lock()) {
+            on_commit(offset);
+        }
         Ok(value)
     }
+}
 
-    fn to_rpc_request(&self, request: Request) -> GreptimeRequest {
-        GreptimeRequest {
-            header: Some(RequestHeader {
-                authorization: self.auth_header.clone(),
-                dbname: self.dbname.clone(),
-                ..Default::default()
-            }),
-            request: Some(request),
+impl Sink<RowInsertRequests> for StreamInserter {
+    type Error = Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        this.poll_sender
+            .poll_reserve(cx)
+            .map_err(|e| this.handle.send_failed(e.to_string()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, requests: RowInsertRequests) -> Result<()> {
+        let this = self.get_mut();
+        if this.handle.skip_empty_requests && is_empty(&requests) {
+            return Ok(());
         }
+        let request = this.handle.to_rpc_request(Request::RowInserts(requests));
+        check_message_size(&request, this.handle.max_message_size)?;
+        this.poll_sender
+            .send_item((request, None))
+            .map_err(|e| this.handle.send_failed(e.to_string()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+        // Requests are handed over to the streaming task in `start_send`.
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.get_mut().poll_sender.close();
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::BufferBudget;
+
+    #[tokio::test]
+    async fn test_buffer_budget() {
+        let budget = BufferBudget::new(100).unwrap();
+
+        let first = budget.acquire(60).await;
+        let second = tokio::time::timeout(Duration::from_millis(50), budget.acquire(60)).await;
+        assert!(second.is_err());
+
+        drop(first);
+        let second = budget.acquire(60).await;
+        drop(second);
+
+        // Larger than the whole budget, admitted alone.
+        let _large = budget.acquire(1000).await;
+        assert_eq!(0, budget.semaphore.available_permits());
+    }
+
+    #[test]
+    fn test_buffer_budget_bounds() {
+        assert!(BufferBudget::new(0).is_none());
+        assert_eq!(u32::MAX, BufferBudget::new(usize::MAX).unwrap().max_bytes);
     }
 }